@@ -0,0 +1,487 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    io::Write,
+    path::Path,
+    time::Duration,
+};
+
+use ash::vk;
+use nom::Parser;
+
+/// v2 added the environment field; v1 records still load, with none.
+const HEADER: &str = "# vk-compute-shader-testing baseline v2";
+
+/// Which device and driver a result was produced on.
+///
+/// `name` and `driver` are the key, `info` is the driver build (the Mesa
+/// version for Mesa drivers) and is only reported.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DeviceIdentity {
+    pub name: String,
+    pub driver: String,
+    pub info: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    Fail(vk::Result),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Pass => write!(f, "pass"),
+            Outcome::Fail(result) => write!(f, "fail ({:?})", result),
+        }
+    }
+}
+
+/// One shader compile from a batch run.
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub hash: u64,
+    pub device: DeviceIdentity,
+    pub shader: String,
+    pub outcome: Outcome,
+    pub compile_time: Duration,
+    /// The driver relevant environment of the run, see `env::effective`.
+    pub environment: Vec<(String, String)>,
+}
+
+/// FNV-1a, stable across toolchains unlike `DefaultHasher`.
+pub fn hash_bytes(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// [`hash_bytes`] over the SPIR-V words.
+pub fn hash_code(code: &[u32]) -> u64 {
+    hash_bytes(code.iter().flat_map(|word| word.to_le_bytes()))
+}
+
+/// Percent-escapes what separates fields and records.
+fn escape_field(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\t', "%09")
+        .replace('\n', "%0a")
+}
+
+/// [`escape_field`], and spaces, which separate variables.
+fn escape(s: &str) -> String {
+    escape_field(s).replace(' ', "%20")
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("%20", " ")
+        .replace("%09", "\t")
+        .replace("%0a", "\n")
+        .replace("%25", "%")
+}
+
+/// `KEY=value` pairs separated by spaces.
+fn format_environment(environment: &[(String, String)]) -> String {
+    environment
+        .iter()
+        .map(|(key, value)| format!("{}={}", escape(key), escape(value)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn parse_environment(field: &str) -> Option<Vec<(String, String)>> {
+    field
+        .split(' ')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            Some((unescape(key), unescape(value)))
+        })
+        .collect()
+}
+
+fn format_record(record: &Record) -> String {
+    format!(
+        "{:016x}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+        record.hash,
+        escape_field(&record.device.name),
+        escape_field(&record.device.driver),
+        escape_field(&record.device.info),
+        escape_field(&record.shader),
+        match record.outcome {
+            Outcome::Pass => "pass".to_string(),
+            Outcome::Fail(result) => format!("fail:{}", result.as_raw()),
+        },
+        record.compile_time.as_nanos(),
+        format_environment(&record.environment)
+    )
+}
+
+pub fn save(path: &Path, records: &[Record]) -> std::io::Result<()> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    writeln!(file, "{}", HEADER)?;
+    for record in records {
+        writeln!(file, "{}", format_record(record))?;
+    }
+    file.flush()
+}
+
+fn parse_record(line: &str) -> Option<Record> {
+    use nom::{
+        branch::alt,
+        bytes::complete::{tag, take_till},
+        character::complete::{char, digit1, hex_digit1, i32},
+        combinator::{all_consuming, map, map_res, opt, rest},
+        error::Error,
+        sequence::{preceded, terminated, tuple},
+    };
+    // Fields can be empty, e.g. `info` from a driver that reports none.
+    let field = || terminated(take_till::<_, _, Error<_>>(|c| c == '\t'), char('\t'));
+    let (_, (hash, name, driver, info, shader, outcome, nanos, environment)) =
+        all_consuming(tuple((
+            terminated(
+                map_res(hex_digit1, |s| u64::from_str_radix(s, 16)),
+                char('\t'),
+            ),
+            field(),
+            field(),
+            field(),
+            field(),
+            terminated(
+                alt((
+                    map(tag("pass"), |_| Outcome::Pass),
+                    map(preceded(tag("fail:"), i32), |raw| {
+                        Outcome::Fail(vk::Result::from_raw(raw))
+                    }),
+                )),
+                char('\t'),
+            ),
+            map_res(digit1, str::parse::<u64>),
+            opt(preceded(char('\t'), rest)),
+        )))
+        .parse(line)
+        .ok()?;
+    Some(Record {
+        hash,
+        device: DeviceIdentity {
+            name: unescape(name),
+            driver: unescape(driver),
+            info: unescape(info),
+        },
+        shader: unescape(shader),
+        outcome,
+        compile_time: Duration::from_nanos(nanos),
+        environment: parse_environment(environment.unwrap_or_default())?,
+    })
+}
+
+pub fn load(path: &Path) -> std::io::Result<Vec<Record>> {
+    let text = std::fs::read_to_string(path)?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| {
+            parse_record(line).ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "{}:{}: malformed baseline record",
+                        path.display(),
+                        number + 1
+                    ),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Differences between a baseline and the current run.
+#[derive(Default)]
+pub struct Comparison {
+    pub newly_failing: Vec<(Record, Record)>,
+    pub newly_passing: Vec<(Record, Record)>,
+    pub regressions: Vec<(Record, Record)>,
+    pub unmatched: Vec<Record>,
+    /// Baseline records of a shader selected for the current run, on a device
+    /// and driver in it, that the run has no record for.
+    pub missing: Vec<Record>,
+}
+
+impl Comparison {
+    pub fn is_clean(&self) -> bool {
+        self.newly_failing.is_empty() && self.regressions.is_empty() && self.missing.is_empty()
+    }
+}
+
+/// Matches records on shader hash and device/driver identity; a passing compile
+/// counts as a regression when it got slower by more than `threshold` percent.
+/// Baseline records of other devices or drivers are not compared, and
+/// records of shaders outside `selected` are not missed.
+pub fn compare(
+    baseline: &[Record],
+    current: &[Record],
+    selected: &[&str],
+    threshold: f64,
+) -> Comparison {
+    let key = |record: &Record| {
+        (
+            record.hash,
+            record.device.name.clone(),
+            record.device.driver.clone(),
+        )
+    };
+    let current_keys = current.iter().map(key).collect::<HashSet<_>>();
+    let missing = baseline
+        .iter()
+        .filter(|old| {
+            selected.contains(&old.shader.as_str())
+                && current.iter().any(|record| {
+                    record.device.name == old.device.name
+                        && record.device.driver == old.device.driver
+                })
+                && !current_keys.contains(&key(old))
+        })
+        .cloned()
+        .collect();
+    let baseline: HashMap<_, _> = baseline
+        .iter()
+        .map(|record| (key(record), record))
+        .collect();
+
+    let mut comparison = Comparison {
+        missing,
+        ..Default::default()
+    };
+    for record in current {
+        let Some(&old) = baseline.get(&key(record)) else {
+            comparison.unmatched.push(record.clone());
+            continue;
+        };
+        let pair = (old.clone(), record.clone());
+        match (old.outcome, record.outcome) {
+            (Outcome::Pass, Outcome::Fail(_)) => comparison.newly_failing.push(pair),
+            (Outcome::Fail(_), Outcome::Pass) => comparison.newly_passing.push(pair),
+            (Outcome::Pass, Outcome::Pass)
+                if record.compile_time.as_secs_f64()
+                    > old.compile_time.as_secs_f64() * (1. + threshold / 100.) =>
+            {
+                comparison.regressions.push(pair)
+            }
+            _ => {}
+        }
+    }
+    comparison
+}
+
+/// Notes a change of environment between the runs of a pair, which may be
+/// what changed the outcome.
+fn report_environment(old: &Record, new: &Record) {
+    if old.environment != new.environment {
+        println!(
+            "    environment: {}, was {}",
+            format_environment(&new.environment),
+            format_environment(&old.environment)
+        );
+    }
+}
+
+pub fn report(comparison: &Comparison) {
+    for (old, new) in &comparison.newly_failing {
+        println!(
+            "newly failing: {} on {} ({}): {} with {}, was {} with {}",
+            new.shader,
+            new.device.name,
+            new.device.driver,
+            new.outcome,
+            new.device.info,
+            old.outcome,
+            old.device.info
+        );
+        report_environment(old, new);
+    }
+    for (old, new) in &comparison.newly_passing {
+        println!(
+            "newly passing: {} on {} ({}): {} with {}, was {} with {}",
+            new.shader,
+            new.device.name,
+            new.device.driver,
+            new.outcome,
+            new.device.info,
+            old.outcome,
+            old.device.info
+        );
+        report_environment(old, new);
+    }
+    for (old, new) in &comparison.regressions {
+        println!(
+            "compile time regression: {} on {} ({}): {:?} with {}, was {:?} with {} ({:+.1}%)",
+            new.shader,
+            new.device.name,
+            new.device.driver,
+            new.compile_time,
+            new.device.info,
+            old.compile_time,
+            old.device.info,
+            (new.compile_time.as_secs_f64() / old.compile_time.as_secs_f64() - 1.) * 100.
+        );
+        report_environment(old, new);
+    }
+    for record in &comparison.missing {
+        println!(
+            "missing: {} ({:016x}) on {} ({}) is in the baseline but was not run",
+            record.shader, record.hash, record.device.name, record.device.driver
+        );
+    }
+    for record in &comparison.unmatched {
+        println!(
+            "not in baseline: {} ({:016x}) on {} ({})",
+            record.shader, record.hash, record.device.name, record.device.driver
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(hash: u64, outcome: Outcome, millis: u64) -> Record {
+        Record {
+            hash,
+            device: DeviceIdentity {
+                name: "Intel(R) Arc(tm) A770 Graphics (DG2)".to_string(),
+                driver: "Intel open-source Mesa driver".to_string(),
+                info: String::new(),
+            },
+            shader: "shaders/1.spv".to_string(),
+            outcome,
+            compile_time: Duration::from_millis(millis),
+            environment: vec![],
+        }
+    }
+
+    #[test]
+    fn records_round_trip() {
+        for original in [
+            record(0x0123456789abcdef, Outcome::Pass, 12),
+            record(1, Outcome::Fail(vk::Result::ERROR_UNKNOWN), 0),
+            Record {
+                environment: vec![
+                    ("INTEL_DEBUG".to_string(), "cs,spill_fs".to_string()),
+                    (
+                        "MESA_SHADER_CACHE_DIR".to_string(),
+                        "/tmp/a b%c".to_string(),
+                    ),
+                ],
+                ..record(2, Outcome::Pass, 3)
+            },
+            Record {
+                device: DeviceIdentity {
+                    name: "GPU\t0".to_string(),
+                    driver: "100% Mesa".to_string(),
+                    info: "Mesa 24.1\ngit".to_string(),
+                },
+                shader: "a\tb.spv".to_string(),
+                ..record(3, Outcome::Pass, 4)
+            },
+        ] {
+            let parsed = parse_record(&format_record(&original)).unwrap();
+            assert_eq!(parsed.hash, original.hash);
+            assert_eq!(parsed.device, original.device);
+            assert_eq!(parsed.shader, original.shader);
+            assert_eq!(parsed.outcome, original.outcome);
+            assert_eq!(parsed.compile_time, original.compile_time);
+            assert_eq!(parsed.environment, original.environment);
+        }
+    }
+
+    #[test]
+    fn v1_records_load_without_environment() {
+        let record = parse_record("0000000000000001\ta\tb\t\td\tpass\t5").unwrap();
+        assert_eq!(record.device.info, "");
+        assert!(record.environment.is_empty());
+    }
+
+    #[test]
+    fn malformed_records_are_rejected() {
+        assert!(parse_record("").is_none());
+        assert!(parse_record("xyz\ta\tb\tc\td\tpass\t1").is_none());
+        assert!(parse_record("1\ta\tb\tc\td\tmaybe\t1").is_none());
+        assert!(parse_record("1\ta\tb\tc\tpass\t1").is_none());
+    }
+
+    #[test]
+    fn compare_sorts_out_changes() {
+        let baseline = [
+            record(1, Outcome::Pass, 10),
+            record(2, Outcome::Pass, 10),
+            record(3, Outcome::Fail(vk::Result::ERROR_UNKNOWN), 10),
+            record(4, Outcome::Pass, 10),
+            record(5, Outcome::Pass, 10),
+        ];
+        let current = [
+            record(1, Outcome::Fail(vk::Result::ERROR_UNKNOWN), 10),
+            record(2, Outcome::Pass, 20),
+            record(3, Outcome::Pass, 10),
+            record(4, Outcome::Pass, 10),
+            record(6, Outcome::Pass, 10),
+        ];
+        let comparison = compare(&baseline, &current, &["shaders/1.spv"], 10.);
+        let hashes =
+            |pairs: &[(Record, Record)]| pairs.iter().map(|(_, new)| new.hash).collect::<Vec<_>>();
+        assert_eq!(hashes(&comparison.newly_failing), [1]);
+        assert_eq!(hashes(&comparison.regressions), [2]);
+        assert_eq!(hashes(&comparison.newly_passing), [3]);
+        assert_eq!(
+            comparison
+                .unmatched
+                .iter()
+                .map(|r| r.hash)
+                .collect::<Vec<_>>(),
+            [6]
+        );
+        assert_eq!(
+            comparison
+                .missing
+                .iter()
+                .map(|r| r.hash)
+                .collect::<Vec<_>>(),
+            [5]
+        );
+        assert!(!comparison.is_clean());
+    }
+
+    #[test]
+    fn other_devices_are_not_missing() {
+        let mut other = record(1, Outcome::Pass, 10);
+        other.device.driver = "radv".to_string();
+        let comparison = compare(
+            &[other],
+            &[record(2, Outcome::Pass, 10)],
+            &["shaders/1.spv"],
+            10.,
+        );
+        assert!(comparison.missing.is_empty());
+        assert!(comparison.is_clean());
+    }
+
+    #[test]
+    fn unselected_shaders_are_not_missing() {
+        let other = Record {
+            shader: "shaders/2.spv".to_string(),
+            ..record(2, Outcome::Pass, 10)
+        };
+        let current = [record(1, Outcome::Pass, 10)];
+        let baseline = [record(1, Outcome::Pass, 10), other];
+        let comparison = compare(&baseline, &current, &["shaders/1.spv"], 10.);
+        assert!(comparison.missing.is_empty());
+        assert!(comparison.is_clean());
+        let comparison = compare(
+            &baseline,
+            &current,
+            &["shaders/1.spv", "shaders/2.spv"],
+            10.,
+        );
+        assert_eq!(comparison.missing.len(), 1);
+    }
+}
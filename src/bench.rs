@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+/// Summary of repeated compile timings.
+#[derive(Clone, Copy, Debug)]
+pub struct Stats {
+    pub samples: usize,
+    pub min: Duration,
+    pub median: Duration,
+    pub mean: Duration,
+    pub p95: Duration,
+    pub std_dev: Duration,
+}
+
+impl Stats {
+    /// Returns `None` for an empty sample set.
+    pub fn new(samples: &[Duration]) -> Option<Self> {
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+        let len = sorted.len();
+        if len == 0 {
+            return None;
+        }
+
+        let mean = sorted.iter().map(Duration::as_secs_f64).sum::<f64>() / len as f64;
+        let variance = sorted
+            .iter()
+            .map(|sample| (sample.as_secs_f64() - mean).powi(2))
+            .sum::<f64>()
+            / len as f64;
+        let median = if len.is_multiple_of(2) {
+            (sorted[len / 2 - 1] + sorted[len / 2]) / 2
+        } else {
+            sorted[len / 2]
+        };
+        // Nearest-rank percentile.
+        let p95 = sorted[((len as f64 * 0.95).ceil() as usize).clamp(1, len) - 1];
+
+        Some(Stats {
+            samples: len,
+            min: sorted[0],
+            median,
+            mean: Duration::from_secs_f64(mean),
+            p95,
+            std_dev: Duration::from_secs_f64(variance.sqrt()),
+        })
+    }
+}
+
+pub fn report(shader: &str, stats: &Stats) {
+    println!(
+        "{}: n={} min={:?} median={:?} mean={:?} p95={:?} stddev={:?}",
+        shader, stats.samples, stats.min, stats.median, stats.mean, stats.p95, stats.std_dev
+    );
+}
@@ -0,0 +1,290 @@
+use std::{fmt, path::Path};
+
+use ash::vk;
+use nom::Parser;
+
+use crate::{
+    error::{Error, Result},
+    memory,
+    owned::Owned,
+};
+
+/// `VkPipelineCacheHeaderVersionOne`, which every driver has to put in front
+/// of its cache data. The fields are little endian regardless of the host.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub header_size: u32,
+    pub header_version: vk::PipelineCacheHeaderVersion,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub uuid: [u8; vk::UUID_SIZE],
+}
+
+impl Header {
+    pub const SIZE: usize = 16 + vk::UUID_SIZE;
+
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        use nom::{bytes::complete::take, error::Error, number::complete::le_u32, sequence::tuple};
+        let (_, (header_size, header_version, vendor_id, device_id, uuid)) = tuple((
+            le_u32::<_, Error<_>>,
+            le_u32,
+            le_u32,
+            le_u32,
+            take(vk::UUID_SIZE),
+        ))
+        .parse(data)
+        .ok()?;
+        Some(Header {
+            header_size,
+            header_version: vk::PipelineCacheHeaderVersion::from_raw(header_version as i32),
+            vendor_id,
+            device_id,
+            uuid: uuid.try_into().unwrap(),
+        })
+    }
+
+    /// Lists every field that keeps a device from accepting this cache.
+    pub fn mismatches(&self, properties: &vk::PhysicalDeviceProperties) -> Vec<String> {
+        let mut mismatches = vec![];
+        if self.header_size as usize != Self::SIZE {
+            mismatches.push(format!(
+                "header size {} (expected {})",
+                self.header_size,
+                Self::SIZE
+            ));
+        }
+        if self.header_version != vk::PipelineCacheHeaderVersion::ONE {
+            mismatches.push(format!("header version {:?}", self.header_version));
+        }
+        if self.vendor_id != properties.vendor_id {
+            mismatches.push(format!(
+                "vendor ID {:#06x} (device has {:#06x})",
+                self.vendor_id, properties.vendor_id
+            ));
+        }
+        if self.device_id != properties.device_id {
+            mismatches.push(format!(
+                "device ID {:#06x} (device has {:#06x})",
+                self.device_id, properties.device_id
+            ));
+        }
+        if self.uuid != properties.pipeline_cache_uuid {
+            mismatches.push(format!(
+                "UUID {} (device has {})",
+                Uuid(&self.uuid),
+                Uuid(&properties.pipeline_cache_uuid)
+            ));
+        }
+        mismatches
+    }
+}
+
+/// Formats a UUID the way `vulkaninfo` does.
+pub struct Uuid<'a>(pub &'a [u8; vk::UUID_SIZE]);
+
+impl fmt::Display for Uuid<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if [4, 6, 8, 10].contains(&i) {
+                write!(f, "-")?;
+            }
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Creates a pipeline cache seeded from `path` when that file exists and its
+/// header matches the device. Data for another device is dropped with a
+/// warning, since the driver would ignore it anyway.
+pub fn create<'a>(
+    device: &'a ash::Device,
+    properties: &vk::PhysicalDeviceProperties,
+    path: &Path,
+) -> Result<Owned<'a, vk::PipelineCache>> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+        Err(e) => return Err(Error::io(path)(e)),
+    };
+
+    let data = if data.is_empty() {
+        data
+    } else {
+        match Header::parse(&data).map(|header| header.mismatches(properties)) {
+            None => {
+                eprintln!(
+                    "{}: too short for a pipeline cache header, ignoring",
+                    path.display()
+                );
+                vec![]
+            }
+            Some(mismatches) if !mismatches.is_empty() => {
+                for mismatch in mismatches {
+                    eprintln!("{}: {}", path.display(), mismatch);
+                }
+                eprintln!("{}: not for this device, ignoring", path.display());
+                vec![]
+            }
+            Some(_) => data,
+        }
+    };
+
+    let create_info = vk::PipelineCacheCreateInfo::builder().initial_data(&data);
+    let pipeline_cache = unsafe { device.create_pipeline_cache(&create_info, memory::callbacks()) }
+        .map_err(Error::vulkan("vkCreatePipelineCache"))?;
+    Ok(Owned::new(
+        device,
+        pipeline_cache,
+        ash::Device::destroy_pipeline_cache,
+    ))
+}
+
+pub fn save(device: &ash::Device, pipeline_cache: vk::PipelineCache, path: &Path) -> Result<()> {
+    let data = unsafe { device.get_pipeline_cache_data(pipeline_cache) }
+        .map_err(Error::vulkan("vkGetPipelineCacheData"))?;
+    std::fs::write(path, data).map_err(Error::io(path))
+}
+
+/// How much of the payload `inspect` hexdumps.
+const HEXDUMP_LIMIT: usize = 256;
+
+/// Prints the header of the cache file at `path`, checks it against every
+/// physical device and summarizes the driver specific payload.
+pub fn inspect(
+    instance: &ash::Instance,
+    physical_devices: &[vk::PhysicalDevice],
+    path: &Path,
+) -> Result<()> {
+    let data = std::fs::read(path).map_err(Error::io(path))?;
+    let Some(header) = Header::parse(&data) else {
+        println!(
+            "{}: {} bytes, too short for a pipeline cache header",
+            path.display(),
+            data.len()
+        );
+        return Ok(());
+    };
+
+    println!("{}: {} bytes", path.display(), data.len());
+    println!("header size:    {}", header.header_size);
+    println!("header version: {:?}", header.header_version);
+    println!("vendor ID:      {:#06x}", header.vendor_id);
+    println!("device ID:      {:#06x}", header.device_id);
+    println!("UUID:           {}", Uuid(&header.uuid));
+
+    for &physical_device in physical_devices {
+        let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        let name = unsafe { std::ffi::CStr::from_ptr(properties.device_name.as_ptr()) };
+        let mismatches = header.mismatches(&properties);
+        if mismatches.is_empty() {
+            println!("{}: matches", name.to_string_lossy());
+        } else {
+            println!("{}: {}", name.to_string_lossy(), mismatches.join(", "));
+        }
+    }
+
+    let payload = &data[(header.header_size as usize).min(data.len())..];
+    let mut counts = [0usize; 256];
+    for &byte in payload {
+        counts[byte as usize] += 1;
+    }
+    let entropy = counts
+        .iter()
+        .filter(|&&count| count != 0)
+        .map(|&count| {
+            let p = count as f64 / payload.len() as f64;
+            -p * p.log2()
+        })
+        .sum::<f64>();
+    println!(
+        "payload: {} bytes, {} zero, {:.2} bits/byte",
+        payload.len(),
+        counts[0],
+        entropy
+    );
+
+    for (i, line) in payload[..payload.len().min(HEXDUMP_LIMIT)]
+        .chunks(16)
+        .enumerate()
+    {
+        let hex = line
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let ascii = line
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect::<String>();
+        println!("{:08x}  {:<47}  |{}|", i * 16, hex, ascii);
+    }
+    if payload.len() > HEXDUMP_LIMIT {
+        println!("... {} more bytes", payload.len() - HEXDUMP_LIMIT);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header_bytes(header_size: u32, vendor_id: u32) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend(header_size.to_le_bytes());
+        data.extend(1u32.to_le_bytes());
+        data.extend(vendor_id.to_le_bytes());
+        data.extend(0x56a0u32.to_le_bytes());
+        data.extend(0..vk::UUID_SIZE as u8);
+        data
+    }
+
+    #[test]
+    fn parses_little_endian_fields() {
+        let mut data = header_bytes(Header::SIZE as u32, 0x8086);
+        data.extend([0xff; 64]);
+        let header = Header::parse(&data).unwrap();
+        assert_eq!(header.header_size, 32);
+        assert_eq!(header.header_version, vk::PipelineCacheHeaderVersion::ONE);
+        assert_eq!(header.vendor_id, 0x8086);
+        assert_eq!(header.device_id, 0x56a0);
+        assert_eq!(header.uuid[15], 15);
+    }
+
+    #[test]
+    fn short_data_is_not_a_header() {
+        let data = header_bytes(Header::SIZE as u32, 0x8086);
+        assert!(Header::parse(&data[..Header::SIZE - 1]).is_none());
+        assert!(Header::parse(&[]).is_none());
+    }
+
+    #[test]
+    fn mismatches_name_each_field() {
+        let header = Header::parse(&header_bytes(48, 0x1002)).unwrap();
+        let properties = vk::PhysicalDeviceProperties {
+            vendor_id: 0x8086,
+            device_id: 0x56a0,
+            pipeline_cache_uuid: header.uuid,
+            ..Default::default()
+        };
+        let mismatches = header.mismatches(&properties);
+        assert_eq!(mismatches.len(), 2, "{:?}", mismatches);
+        assert!(mismatches[0].starts_with("header size 48"));
+        assert!(mismatches[1].starts_with("vendor ID 0x1002"));
+    }
+
+    #[test]
+    fn uuids_format_like_vulkaninfo() {
+        let uuid = [0xab; vk::UUID_SIZE];
+        assert_eq!(
+            Uuid(&uuid).to_string(),
+            "abababab-abab-abab-abab-abababababab"
+        );
+    }
+}
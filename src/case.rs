@@ -0,0 +1,191 @@
+use std::path::Path;
+
+use nom::Parser;
+
+use crate::error::{Error, Result};
+
+/// A repro case file: the command line options for a run plus named sections
+/// for anything that does not fit on a command line.
+///
+/// ```text
+/// # comments start with '#' at the start of a line or after whitespace
+/// preset = starfield
+/// stats
+///
+/// [env intel-spill]
+/// INTEL_DEBUG = cs,spill_fs
+/// ```
+///
+/// Top level `key = value` lines become `--key=value` options and a bare
+/// `key` becomes `--key`, so any `--` option but `--case=` itself can be set
+/// here. The mode, `-v` and `-N` can only be given on the command line.
+/// Options given on the command line after `--case=` win.
+#[derive(Clone, Debug, Default)]
+pub struct Case {
+    pub options: Vec<(String, Option<String>)>,
+    pub sections: Vec<Section>,
+}
+
+/// `[kind name]` followed by `key = value` lines.
+#[derive(Clone, Debug, Default)]
+pub struct Section {
+    pub kind: String,
+    pub name: String,
+    pub entries: Vec<(String, String)>,
+}
+
+enum Line<'a> {
+    Blank,
+    Header(&'a str, &'a str),
+    Entry(&'a str, Option<&'a str>),
+}
+
+fn parse_line(line: &str) -> Option<Line<'_>> {
+    use nom::{
+        branch::alt,
+        bytes::complete::{is_not, take_till1},
+        character::complete::{char, space0, space1},
+        combinator::{all_consuming, map, opt, rest, verify},
+        error::Error,
+        sequence::{delimited, preceded, separated_pair, terminated, tuple},
+    };
+    // `#` starts a comment at the start of a line or after whitespace, so
+    // one inside a value like `key=a#b` stays.
+    let comment = line
+        .char_indices()
+        .find(|&(index, c)| {
+            c == '#'
+                && line[..index]
+                    .chars()
+                    .next_back()
+                    .is_none_or(char::is_whitespace)
+        })
+        .map_or(line.len(), |(index, _)| index);
+    let line = line[..comment].trim();
+    if line.is_empty() {
+        return Some(Line::Blank);
+    }
+    all_consuming(alt((
+        map(
+            delimited(
+                char::<_, Error<_>>('['),
+                separated_pair(
+                    take_till1(|c: char| c.is_whitespace() || c == ']'),
+                    space1,
+                    is_not("]"),
+                ),
+                char(']'),
+            ),
+            |(kind, name): (&str, &str)| Line::Header(kind, name.trim()),
+        ),
+        map(
+            tuple((
+                // A broken section header is not an option.
+                terminated(
+                    verify(
+                        take_till1(|c: char| c.is_whitespace() || c == '='),
+                        |key: &str| !key.starts_with('['),
+                    ),
+                    space0,
+                ),
+                opt(preceded(terminated(char('='), space0), rest)),
+            )),
+            |(key, value)| Line::Entry(key, value),
+        ),
+    )))
+    .parse(line)
+    .ok()
+    .map(|(_, line)| line)
+}
+
+impl Case {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).map_err(Error::io(path))?;
+        let mut case = Case::default();
+        for (number, line) in text.lines().enumerate() {
+            match parse_line(line) {
+                Some(Line::Blank) => {}
+                Some(Line::Header(kind, name)) => case.sections.push(Section {
+                    kind: kind.to_string(),
+                    name: name.to_string(),
+                    entries: vec![],
+                }),
+                Some(Line::Entry(key, value)) => match case.sections.last_mut() {
+                    Some(section) => section
+                        .entries
+                        .push((key.to_string(), value.unwrap_or_default().to_string())),
+                    None => case
+                        .options
+                        .push((key.to_string(), value.map(str::to_string))),
+                },
+                None => {
+                    return Err(Error::Usage(format!(
+                        "{}:{}: not understood: {}",
+                        path.display(),
+                        number + 1,
+                        line
+                    )))
+                }
+            }
+        }
+        Ok(case)
+    }
+
+    /// The top level options as command line arguments.
+    pub fn args(&self) -> impl Iterator<Item = String> + '_ {
+        self.options.iter().map(|(key, value)| match value {
+            Some(value) => format!("--{}={}", key, value),
+            None => format!("--{}", key),
+        })
+    }
+
+    pub fn section(&self, kind: &str, name: &str) -> Option<&Section> {
+        self.sections
+            .iter()
+            .find(|section| section.kind == kind && section.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(line: &str) -> Option<(&str, Option<&str>)> {
+        match parse_line(line)? {
+            Line::Entry(key, value) => Some((key, value)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn lines() {
+        assert!(matches!(parse_line(""), Some(Line::Blank)));
+        assert!(matches!(parse_line("   # a comment"), Some(Line::Blank)));
+        assert!(matches!(
+            parse_line("[env intel spill ]"),
+            Some(Line::Header("env", "intel spill"))
+        ));
+        assert_eq!(entry("stats"), Some(("stats", None)));
+        assert_eq!(
+            entry("preset = starfield"),
+            Some(("preset", Some("starfield")))
+        );
+        assert_eq!(
+            entry("preset=starfield # why"),
+            Some(("preset", Some("starfield")))
+        );
+        assert_eq!(entry("a = b#c #d"), Some(("a", Some("b#c"))));
+        assert_eq!(entry("a=#b"), Some(("a", Some("#b"))));
+        assert_eq!(
+            entry("INTEL_DEBUG = cs,spill_fs"),
+            Some(("INTEL_DEBUG", Some("cs,spill_fs")))
+        );
+    }
+
+    #[test]
+    fn malformed_lines() {
+        assert!(parse_line("[env]").is_none());
+        assert!(parse_line("[env name").is_none());
+        assert!(parse_line("two words").is_none());
+    }
+}
@@ -0,0 +1,335 @@
+use std::ffi::{CStr, CString};
+
+use ash::{extensions::khr, vk};
+
+use crate::{
+    baseline,
+    error::{Error, Result},
+    identity::Identity,
+    memory,
+};
+
+fn string(chars: &[std::ffi::c_char]) -> String {
+    unsafe { CStr::from_ptr(chars.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Whether `physical_device` has device extension `name`.
+fn has_extension(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    name: &CStr,
+) -> Result<bool> {
+    Ok(
+        unsafe { instance.enumerate_device_extension_properties(physical_device) }
+            .map_err(Error::vulkan("vkEnumerateDeviceExtensionProperties"))?
+            .iter()
+            .any(|extension| {
+                let extension_name = unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) };
+                extension_name == name
+            }),
+    )
+}
+
+/// What the pipeline flag matrix needs from a device, from Vulkan 1.3 or,
+/// before it, `VK_EXT_subgroup_size_control` and
+/// `VK_EXT_pipeline_creation_cache_control`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SubgroupSupport {
+    /// Whether the device is used at 1.3, as [`Instance::api_version`] has
+    /// it.
+    pub vulkan13: bool,
+    /// The extensions, when the device is older than 1.3.
+    pub size_control_extension: bool,
+    pub cache_control_extension: bool,
+    pub subgroup_size_control: bool,
+    pub compute_full_subgroups: bool,
+    pub pipeline_creation_cache_control: bool,
+    pub min_subgroup_size: u32,
+    pub max_subgroup_size: u32,
+    pub required_subgroup_size_stages: vk::ShaderStageFlags,
+}
+
+impl SubgroupSupport {
+    pub fn query(instance: &Instance, physical_device: vk::PhysicalDevice) -> Result<Self> {
+        let vulkan13 = instance.api_version(physical_device) >= vk::API_VERSION_1_3;
+        let instance = &instance.instance;
+        let size_control_extension = !vulkan13
+            && has_extension(
+                instance,
+                physical_device,
+                vk::ExtSubgroupSizeControlFn::name(),
+            )?;
+        let cache_control_extension = !vulkan13
+            && has_extension(
+                instance,
+                physical_device,
+                vk::ExtPipelineCreationCacheControlFn::name(),
+            )?;
+
+        let mut size_control = vk::PhysicalDeviceSubgroupSizeControlFeatures::default();
+        let mut cache_control = vk::PhysicalDevicePipelineCreationCacheControlFeatures::default();
+        let mut features = vk::PhysicalDeviceFeatures2::builder();
+        if vulkan13 || size_control_extension {
+            features = features.push_next(&mut size_control);
+        }
+        if vulkan13 || cache_control_extension {
+            features = features.push_next(&mut cache_control);
+        }
+        unsafe { instance.get_physical_device_features2(physical_device, &mut features) };
+
+        let mut size_properties = vk::PhysicalDeviceSubgroupSizeControlProperties::default();
+        let mut properties = vk::PhysicalDeviceProperties2::builder();
+        if vulkan13 || size_control_extension {
+            properties = properties.push_next(&mut size_properties);
+        }
+        unsafe { instance.get_physical_device_properties2(physical_device, &mut properties) };
+
+        Ok(SubgroupSupport {
+            vulkan13,
+            size_control_extension,
+            cache_control_extension,
+            subgroup_size_control: size_control.subgroup_size_control == vk::TRUE,
+            compute_full_subgroups: size_control.compute_full_subgroups == vk::TRUE,
+            pipeline_creation_cache_control: cache_control.pipeline_creation_cache_control
+                == vk::TRUE,
+            min_subgroup_size: size_properties.min_subgroup_size,
+            max_subgroup_size: size_properties.max_subgroup_size,
+            required_subgroup_size_stages: size_properties.required_subgroup_size_stages,
+        })
+    }
+}
+
+/// A Vulkan instance and its physical devices, the preferred one first.
+pub struct Instance {
+    pub entry: ash::Entry,
+    pub instance: ash::Instance,
+    pub physical_devices: Vec<vk::PhysicalDevice>,
+    /// `VkApplicationInfo::apiVersion`, the highest version devices can be
+    /// used at.
+    pub api_version: u32,
+}
+
+impl Instance {
+    pub fn new(identity: &Identity, validation: bool) -> Result<Self> {
+        let app_name = CString::new(identity.application_name.as_str())
+            .map_err(|_| Error::Usage("application name contains NUL".into()))?;
+        let engine_name = CString::new(identity.engine_name.as_str())
+            .map_err(|_| Error::Usage("engine name contains NUL".into()))?;
+        let app_info = vk::ApplicationInfo::builder()
+            .application_name(&app_name)
+            .application_version(identity.application_version)
+            .engine_name(&engine_name)
+            .engine_version(identity.engine_version)
+            .api_version(identity.api_version);
+
+        let entry = unsafe { ash::Entry::load() }.map_err(Error::Loader)?;
+
+        let mut enabled_layer_names = vec![];
+        if validation {
+            enabled_layer_names.push(c"VK_LAYER_KHRONOS_validation".as_ptr())
+        }
+
+        let create_info = vk::InstanceCreateInfo::builder()
+            .application_info(&app_info)
+            .enabled_layer_names(&enabled_layer_names);
+
+        let instance = unsafe { entry.create_instance(&create_info, memory::callbacks()) }
+            .map_err(Error::Instance)?;
+
+        let mut physical_devices = unsafe { instance.enumerate_physical_devices() }
+            .map_err(Error::vulkan("vkEnumeratePhysicalDevices"))?;
+
+        physical_devices.sort_by_key(|physical_device| {
+            let mut device_prop = vk::PhysicalDeviceDriverProperties::builder();
+            let mut prop = vk::PhysicalDeviceProperties2::builder().push_next(&mut device_prop);
+            unsafe { instance.get_physical_device_properties2(*physical_device, &mut prop) };
+
+            match device_prop.driver_id {
+                vk::DriverId::MESA_LLVMPIPE => 100,
+                vk::DriverId::INTEL_OPEN_SOURCE_MESA => -100,
+                _ => -10,
+            }
+        });
+
+        Ok(Instance {
+            entry,
+            instance,
+            physical_devices,
+            api_version: identity.api_version,
+        })
+    }
+
+    /// The version `physical_device` can be used at: the lower of what it
+    /// supports and what the instance was created for.
+    pub fn api_version(&self, physical_device: vk::PhysicalDevice) -> u32 {
+        let device = unsafe {
+            self.instance
+                .get_physical_device_properties(physical_device)
+        }
+        .api_version;
+        device.min(self.api_version)
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        unsafe { self.instance.destroy_instance(memory::callbacks()) };
+    }
+}
+
+/// A device on the preferred physical device, with the extensions and
+/// features the captured pipelines need. Objects created from `device`
+/// borrow it, so they are gone before the device and instance are destroyed.
+pub struct Context {
+    pub instance: Instance,
+    pub physical_device: vk::PhysicalDevice,
+    pub device: ash::Device,
+    /// Set when `VK_KHR_pipeline_executable_properties` was asked for and is
+    /// supported.
+    pub executable_loader: Option<khr::PipelineExecutableProperties>,
+    pub push_descriptor: khr::PushDescriptor,
+}
+
+impl Context {
+    pub fn new(instance: Instance, executable_properties: bool) -> Result<Self> {
+        Self::on(instance, 0, executable_properties)
+    }
+
+    /// A context on physical device `index` in order of preference.
+    pub fn on(instance: Instance, index: usize, executable_properties: bool) -> Result<Self> {
+        let physical_device = *instance
+            .physical_devices
+            .get(index)
+            .ok_or(Error::NoDevice)?;
+
+        // I'm just guessing here.
+        let mut enabled_extension_names = vec![
+            khr::Maintenance4::name().as_ptr(),
+            khr::PushDescriptor::name().as_ptr(),
+            c"VK_NV_compute_shader_derivatives".as_ptr(),
+        ];
+
+        let executable_properties = executable_properties && {
+            let supported = has_extension(
+                &instance.instance,
+                physical_device,
+                khr::PipelineExecutableProperties::name(),
+            )?;
+            if !supported {
+                eprintln!("VK_KHR_pipeline_executable_properties not supported, no statistics");
+            }
+            supported
+        };
+        if executable_properties {
+            enabled_extension_names.push(khr::PipelineExecutableProperties::name().as_ptr());
+        }
+
+        let mut features0 = vk::PhysicalDeviceDescriptorIndexingFeatures::builder()
+            .descriptor_binding_partially_bound(true)
+            .descriptor_binding_variable_descriptor_count(true)
+            .descriptor_binding_update_unused_while_pending(true)
+            .descriptor_binding_sampled_image_update_after_bind(true);
+        let mut features1 = vk::PhysicalDeviceVulkan12Features::builder()
+            .runtime_descriptor_array(true)
+            .buffer_device_address(true);
+        let mut features2 = vk::PhysicalDeviceComputeShaderDerivativesFeaturesNV::builder()
+            .compute_derivative_group_linear(true);
+
+        let temp0 = [*vk::DeviceQueueCreateInfo::builder().queue_priorities(&[1.])];
+
+        let mut features3 = vk::PhysicalDevicePipelineExecutablePropertiesFeaturesKHR::builder()
+            .pipeline_executable_info(true);
+
+        // What the pipeline flag matrix needs, where the device has it.
+        let subgroups = SubgroupSupport::query(&instance, physical_device)?;
+        if subgroups.size_control_extension {
+            enabled_extension_names.push(vk::ExtSubgroupSizeControlFn::name().as_ptr());
+        }
+        if subgroups.cache_control_extension {
+            enabled_extension_names.push(vk::ExtPipelineCreationCacheControlFn::name().as_ptr());
+        }
+        let mut features4 = vk::PhysicalDeviceVulkan13Features::builder()
+            .subgroup_size_control(subgroups.subgroup_size_control)
+            .compute_full_subgroups(subgroups.compute_full_subgroups)
+            .pipeline_creation_cache_control(subgroups.pipeline_creation_cache_control);
+        let mut features5 = vk::PhysicalDeviceSubgroupSizeControlFeatures::builder()
+            .subgroup_size_control(subgroups.subgroup_size_control)
+            .compute_full_subgroups(subgroups.compute_full_subgroups);
+        let mut features6 = vk::PhysicalDevicePipelineCreationCacheControlFeatures::builder()
+            .pipeline_creation_cache_control(subgroups.pipeline_creation_cache_control);
+
+        let mut create_info = vk::DeviceCreateInfo::builder()
+            .queue_create_infos(&temp0)
+            .enabled_extension_names(&enabled_extension_names)
+            .push_next(&mut features0)
+            .push_next(&mut features1)
+            .push_next(&mut features2);
+        if executable_properties {
+            create_info = create_info.push_next(&mut features3);
+        }
+        if subgroups.vulkan13 {
+            create_info = create_info.push_next(&mut features4);
+        }
+        if subgroups.size_control_extension {
+            create_info = create_info.push_next(&mut features5);
+        }
+        if subgroups.cache_control_extension {
+            create_info = create_info.push_next(&mut features6);
+        }
+
+        let device = unsafe {
+            instance
+                .instance
+                .create_device(physical_device, &create_info, memory::callbacks())
+        }
+        .map_err(Error::Device)?;
+
+        let executable_loader = executable_properties
+            .then(|| khr::PipelineExecutableProperties::new(&instance.instance, &device));
+        let push_descriptor = khr::PushDescriptor::new(&instance.instance, &device);
+
+        Ok(Context {
+            instance,
+            physical_device,
+            device,
+            executable_loader,
+            push_descriptor,
+        })
+    }
+
+    pub fn properties(&self) -> vk::PhysicalDeviceProperties {
+        unsafe {
+            self.instance
+                .instance
+                .get_physical_device_properties(self.physical_device)
+        }
+    }
+
+    /// What a baseline record needs to tell devices and driver builds apart.
+    pub fn device_identity(&self) -> baseline::DeviceIdentity {
+        let mut driver_prop = vk::PhysicalDeviceDriverProperties::builder();
+        let mut prop = vk::PhysicalDeviceProperties2::builder().push_next(&mut driver_prop);
+        unsafe {
+            self.instance
+                .instance
+                .get_physical_device_properties2(self.physical_device, &mut prop)
+        };
+
+        baseline::DeviceIdentity {
+            name: string(&prop.properties.device_name),
+            driver: string(&driver_prop.driver_name),
+            info: string(&driver_prop.driver_info),
+        }
+    }
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self.device.device_wait_idle();
+            self.device.destroy_device(memory::callbacks());
+        }
+    }
+}
@@ -0,0 +1,527 @@
+use ash::vk;
+
+use crate::{
+    error::{Error, Result},
+    layout::{Layout, LayoutBuilder, SetLayout},
+    memory,
+    owned::Owned,
+    Context,
+};
+
+/// The descriptor classes the device limits are given for, as they appear in
+/// the limit names.
+const CLASSES: [&str; 6] = [
+    "Samplers",
+    "UniformBuffers",
+    "StorageBuffers",
+    "SampledImages",
+    "StorageImages",
+    "InputAttachments",
+];
+
+/// Which of `CLASSES` a descriptor of `descriptor_type` counts against.
+fn classes(descriptor_type: vk::DescriptorType) -> &'static [usize] {
+    match descriptor_type {
+        vk::DescriptorType::SAMPLER => &[0],
+        vk::DescriptorType::COMBINED_IMAGE_SAMPLER => &[0, 3],
+        vk::DescriptorType::UNIFORM_BUFFER | vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC => &[1],
+        vk::DescriptorType::STORAGE_BUFFER | vk::DescriptorType::STORAGE_BUFFER_DYNAMIC => &[2],
+        vk::DescriptorType::SAMPLED_IMAGE | vk::DescriptorType::UNIFORM_TEXEL_BUFFER => &[3],
+        vk::DescriptorType::STORAGE_IMAGE | vk::DescriptorType::STORAGE_TEXEL_BUFFER => &[4],
+        vk::DescriptorType::INPUT_ATTACHMENT => &[5],
+        _ => &[],
+    }
+}
+
+fn is_update_after_bind(set: &SetLayout) -> bool {
+    set.flags
+        .contains(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
+}
+
+fn is_push(set: &SetLayout) -> bool {
+    set.flags
+        .contains(vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR)
+}
+
+/// The device limits that bound descriptor counts, per class in `CLASSES`
+/// order.
+#[derive(Clone, Debug)]
+pub struct Limits {
+    pub per_set: [u32; 6],
+    pub per_set_update_after_bind: [u32; 6],
+    pub per_stage: [u32; 6],
+    pub per_stage_update_after_bind: [u32; 6],
+    pub update_after_bind_in_all_pools: u32,
+}
+
+/// A limit and the bindings, as (set, binding), that count against it.
+struct Constraint {
+    name: String,
+    max: u32,
+    members: Vec<(usize, usize)>,
+}
+
+impl Constraint {
+    fn total(&self, builder: &LayoutBuilder) -> u64 {
+        self.members
+            .iter()
+            .map(|&(set, binding)| builder.sets[set].bindings[binding].count as u64)
+            .sum()
+    }
+}
+
+impl Limits {
+    /// Reads the limits from `VkPhysicalDeviceVulkan12Properties` when the
+    /// device is used at 1.2 and from the extension structs they replaced
+    /// otherwise.
+    pub fn query(context: &Context) -> Self {
+        let instance = &context.instance.instance;
+        let api_version = context.instance.api_version(context.physical_device);
+        let mut indexing = vk::PhysicalDeviceDescriptorIndexingProperties::default();
+        let mut vulkan12 = vk::PhysicalDeviceVulkan12Properties::default();
+        let mut properties = vk::PhysicalDeviceProperties2::builder();
+        if api_version >= vk::API_VERSION_1_2 {
+            properties = properties.push_next(&mut vulkan12);
+        } else {
+            properties = properties.push_next(&mut indexing);
+        }
+        unsafe {
+            instance.get_physical_device_properties2(context.physical_device, &mut properties)
+        };
+        let limits = properties.properties.limits;
+
+        if api_version >= vk::API_VERSION_1_2 {
+            indexing = vk::PhysicalDeviceDescriptorIndexingProperties {
+                max_update_after_bind_descriptors_in_all_pools: vulkan12
+                    .max_update_after_bind_descriptors_in_all_pools,
+                max_per_stage_descriptor_update_after_bind_samplers: vulkan12
+                    .max_per_stage_descriptor_update_after_bind_samplers,
+                max_per_stage_descriptor_update_after_bind_uniform_buffers: vulkan12
+                    .max_per_stage_descriptor_update_after_bind_uniform_buffers,
+                max_per_stage_descriptor_update_after_bind_storage_buffers: vulkan12
+                    .max_per_stage_descriptor_update_after_bind_storage_buffers,
+                max_per_stage_descriptor_update_after_bind_sampled_images: vulkan12
+                    .max_per_stage_descriptor_update_after_bind_sampled_images,
+                max_per_stage_descriptor_update_after_bind_storage_images: vulkan12
+                    .max_per_stage_descriptor_update_after_bind_storage_images,
+                max_per_stage_descriptor_update_after_bind_input_attachments: vulkan12
+                    .max_per_stage_descriptor_update_after_bind_input_attachments,
+                max_descriptor_set_update_after_bind_samplers: vulkan12
+                    .max_descriptor_set_update_after_bind_samplers,
+                max_descriptor_set_update_after_bind_uniform_buffers: vulkan12
+                    .max_descriptor_set_update_after_bind_uniform_buffers,
+                max_descriptor_set_update_after_bind_storage_buffers: vulkan12
+                    .max_descriptor_set_update_after_bind_storage_buffers,
+                max_descriptor_set_update_after_bind_sampled_images: vulkan12
+                    .max_descriptor_set_update_after_bind_sampled_images,
+                max_descriptor_set_update_after_bind_storage_images: vulkan12
+                    .max_descriptor_set_update_after_bind_storage_images,
+                max_descriptor_set_update_after_bind_input_attachments: vulkan12
+                    .max_descriptor_set_update_after_bind_input_attachments,
+                ..indexing
+            };
+        }
+
+        Limits {
+            per_set: [
+                limits.max_descriptor_set_samplers,
+                limits.max_descriptor_set_uniform_buffers,
+                limits.max_descriptor_set_storage_buffers,
+                limits.max_descriptor_set_sampled_images,
+                limits.max_descriptor_set_storage_images,
+                limits.max_descriptor_set_input_attachments,
+            ],
+            per_set_update_after_bind: [
+                indexing.max_descriptor_set_update_after_bind_samplers,
+                indexing.max_descriptor_set_update_after_bind_uniform_buffers,
+                indexing.max_descriptor_set_update_after_bind_storage_buffers,
+                indexing.max_descriptor_set_update_after_bind_sampled_images,
+                indexing.max_descriptor_set_update_after_bind_storage_images,
+                indexing.max_descriptor_set_update_after_bind_input_attachments,
+            ],
+            per_stage: [
+                limits.max_per_stage_descriptor_samplers,
+                limits.max_per_stage_descriptor_uniform_buffers,
+                limits.max_per_stage_descriptor_storage_buffers,
+                limits.max_per_stage_descriptor_sampled_images,
+                limits.max_per_stage_descriptor_storage_images,
+                limits.max_per_stage_descriptor_input_attachments,
+            ],
+            per_stage_update_after_bind: [
+                indexing.max_per_stage_descriptor_update_after_bind_samplers,
+                indexing.max_per_stage_descriptor_update_after_bind_uniform_buffers,
+                indexing.max_per_stage_descriptor_update_after_bind_storage_buffers,
+                indexing.max_per_stage_descriptor_update_after_bind_sampled_images,
+                indexing.max_per_stage_descriptor_update_after_bind_storage_images,
+                indexing.max_per_stage_descriptor_update_after_bind_input_attachments,
+            ],
+            update_after_bind_in_all_pools: indexing.max_update_after_bind_descriptors_in_all_pools,
+        }
+    }
+
+    /// The limits the layouts in `builder` are subject to. Bindings are
+    /// visible to all stages, so the per stage limits apply to the whole
+    /// layout too. The update after bind limits count all descriptors, the
+    /// others only those outside update after bind sets.
+    fn constraints(&self, builder: &LayoutBuilder) -> Vec<Constraint> {
+        let bindings = |filter: &dyn Fn(&SetLayout) -> bool, class: Option<usize>| {
+            let mut members = vec![];
+            for (set, layout) in builder.sets.iter().enumerate() {
+                if !filter(layout) {
+                    continue;
+                }
+                for (index, binding) in layout.bindings.iter().enumerate() {
+                    let counts = match class {
+                        Some(class) => [binding.descriptor_type]
+                            .iter()
+                            .chain(&binding.mutable_types)
+                            .any(|&descriptor_type| classes(descriptor_type).contains(&class)),
+                        None => true,
+                    };
+                    if counts {
+                        members.push((set, index));
+                    }
+                }
+            }
+            members
+        };
+
+        let mut constraints = vec![];
+        let update_after_bind = builder.sets.iter().any(is_update_after_bind);
+        for (class, name) in CLASSES.iter().enumerate() {
+            let plain = bindings(&|set| !is_update_after_bind(set), Some(class));
+            let all = bindings(&|_| true, Some(class));
+            let mut limits = vec![
+                ("maxDescriptorSet", self.per_set[class], plain.clone()),
+                ("maxPerStageDescriptor", self.per_stage[class], plain),
+            ];
+            if update_after_bind {
+                limits.push((
+                    "maxDescriptorSetUpdateAfterBind",
+                    self.per_set_update_after_bind[class],
+                    all.clone(),
+                ));
+                limits.push((
+                    "maxPerStageDescriptorUpdateAfterBind",
+                    self.per_stage_update_after_bind[class],
+                    all,
+                ));
+            }
+            constraints.extend(limits.into_iter().map(|(prefix, max, members)| Constraint {
+                name: format!("{}{}", prefix, name),
+                max,
+                members,
+            }));
+        }
+        constraints
+    }
+
+    /// Every limit the layouts in `builder` and the pools for them exceed.
+    pub fn check(&self, builder: &LayoutBuilder, pools: &[Pool]) -> Vec<String> {
+        let mut problems = self
+            .constraints(builder)
+            .iter()
+            .filter_map(|constraint| {
+                let total = constraint.total(builder);
+                (total > constraint.max as u64).then(|| {
+                    format!(
+                        "{} descriptors count against {}, which is {}",
+                        total, constraint.name, constraint.max
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let in_pools = pools
+            .iter()
+            .filter(|pool| {
+                pool.flags
+                    .contains(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
+            })
+            .flat_map(|pool| &pool.sizes)
+            .map(|size| size.descriptor_count as u64)
+            .sum::<u64>();
+        if in_pools > self.update_after_bind_in_all_pools as u64 {
+            problems.push(format!(
+                "{} descriptors in update after bind pools, maxUpdateAfterBindDescriptorsInAllPools is {}",
+                in_pools, self.update_after_bind_in_all_pools
+            ));
+        }
+        problems
+    }
+
+    /// Lowers binding counts in `builder`, largest first and never below
+    /// one, until every limit is met, counting the pools as sized by
+    /// default. Returns a line per change, or why it could not be done.
+    pub fn scale(&self, builder: &mut LayoutBuilder) -> std::result::Result<Vec<String>, String> {
+        let mut constraints = self.constraints(builder);
+        constraints.push(Constraint {
+            name: "maxUpdateAfterBindDescriptorsInAllPools".to_string(),
+            max: self.update_after_bind_in_all_pools,
+            members: builder
+                .sets
+                .iter()
+                .enumerate()
+                .filter(|(_, set)| is_update_after_bind(set) && !is_push(set))
+                .flat_map(|(set, layout)| (0..layout.bindings.len()).map(move |index| (set, index)))
+                .collect(),
+        });
+
+        let mut changes = vec![];
+        for constraint in &constraints {
+            let mut members = constraint.members.clone();
+            members.sort_by_key(|&(set, binding)| {
+                std::cmp::Reverse(builder.sets[set].bindings[binding].count)
+            });
+            for (set, index) in members {
+                let total = constraint.total(builder);
+                if total <= constraint.max as u64 {
+                    break;
+                }
+                let binding = &mut builder.sets[set].bindings[index];
+                let excess = total - constraint.max as u64;
+                let count = (binding.count as u64).saturating_sub(excess).max(1) as u32;
+                if count < binding.count {
+                    changes.push(format!(
+                        "set {} binding {}: {} descriptors scaled down to {} for {} ({})",
+                        set, index, binding.count, count, constraint.name, constraint.max
+                    ));
+                    binding.count = count;
+                }
+            }
+            let total = constraint.total(builder);
+            if total > constraint.max as u64 {
+                return Err(format!(
+                    "{} descriptors count against {}, which is {}, even at one per binding",
+                    total, constraint.name, constraint.max
+                ));
+            }
+        }
+        Ok(changes)
+    }
+}
+
+/// What to do when a layout asks for more than the device allows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LimitPolicy {
+    /// Lower the counts to fit, with a warning.
+    Scale,
+    /// Skip the case.
+    Skip,
+    /// Create the layouts as they are and let the driver deal with it.
+    Ignore,
+}
+
+impl LimitPolicy {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "scale" => Some(LimitPolicy::Scale),
+            "skip" => Some(LimitPolicy::Skip),
+            "ignore" => Some(LimitPolicy::Ignore),
+            _ => None,
+        }
+    }
+}
+
+/// The outcome of [`preflight`].
+pub enum Preflight {
+    Fits,
+    /// The counts were lowered; a line per change.
+    Scaled(Vec<String>),
+    /// The case should not be run, and why.
+    Skip(String),
+}
+
+/// Asks the driver whether `set` of `builder` can be created, and the most
+/// descriptors its variable count binding could have.
+fn support(device: &ash::Device, builder: &LayoutBuilder, set: usize) -> (bool, u32) {
+    let mut variable = vk::DescriptorSetVariableDescriptorCountLayoutSupport::default();
+    let mut support = vk::DescriptorSetLayoutSupport::builder().push_next(&mut variable);
+    builder.with_create_info(set, |create_info| unsafe {
+        device.get_descriptor_set_layout_support(create_info, &mut support)
+    });
+    (
+        support.supported == vk::TRUE,
+        variable.max_variable_descriptor_count,
+    )
+}
+
+/// Checks `builder` against the device limits before any layout is created,
+/// then asks the driver about each set, scaling a variable count binding to
+/// what the driver reports it can take. What happens on a problem is up to
+/// `policy`.
+pub fn preflight(context: &Context, builder: &mut LayoutBuilder, policy: LimitPolicy) -> Preflight {
+    if policy == LimitPolicy::Ignore {
+        return Preflight::Fits;
+    }
+    let limits = Limits::query(context);
+    let mut changes = match (policy, limits.scale(&mut builder.clone())) {
+        (_, Err(reason)) => return Preflight::Skip(reason),
+        (LimitPolicy::Skip, Ok(changes)) if !changes.is_empty() => {
+            return Preflight::Skip(changes.join("; "))
+        }
+        _ => limits.scale(builder).unwrap_or_default(),
+    };
+
+    for set in 0..builder.sets.len() {
+        let (supported, max_variable) = support(&context.device, builder, set);
+        if supported {
+            continue;
+        }
+        let variable_binding = builder.sets[set].variable_binding();
+        match variable_binding {
+            Some(index) if policy == LimitPolicy::Scale && max_variable >= 1 => {
+                let binding = &mut builder.sets[set].bindings[index];
+                if max_variable < binding.count {
+                    changes.push(format!(
+                        "set {} binding {}: {} descriptors scaled down to {}, the most the driver supports",
+                        set, index, binding.count, max_variable
+                    ));
+                    binding.count = max_variable;
+                }
+                if !support(&context.device, builder, set).0 {
+                    return Preflight::Skip(format!("set {}: layout not supported", set));
+                }
+            }
+            _ => return Preflight::Skip(format!("set {}: layout not supported", set)),
+        }
+    }
+
+    if changes.is_empty() {
+        Preflight::Fits
+    } else {
+        Preflight::Scaled(changes)
+    }
+}
+
+/// What a pool for one set of a layout has to hold.
+#[derive(Clone, Debug, Default)]
+pub struct Pool {
+    pub flags: vk::DescriptorPoolCreateFlags,
+    pub sizes: Vec<vk::DescriptorPoolSize>,
+    /// The type list for each of `sizes`, empty unless it is `MUTABLE_VALVE`.
+    pub mutable_types: Vec<Vec<vk::DescriptorType>>,
+    /// The count to allocate the variable count binding with.
+    pub variable_count: Option<u32>,
+}
+
+impl Pool {
+    /// Sizes a pool for one `set`. A variable count binding gets
+    /// `variable_count` descriptors, capped at its declared count, or the
+    /// declared count if `None`. Push descriptor sets get an empty pool; they
+    /// are not allocated.
+    pub fn new(set: &SetLayout, variable_count: Option<u32>) -> Self {
+        if is_push(set) {
+            return Pool::default();
+        }
+        let variable_binding = set.variable_binding();
+        let mut pool = Pool {
+            flags: if is_update_after_bind(set) {
+                vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND
+            } else {
+                vk::DescriptorPoolCreateFlags::empty()
+            },
+            ..Pool::default()
+        };
+        for (index, binding) in set.bindings.iter().enumerate() {
+            let count = if Some(index) == variable_binding {
+                let count = variable_count.map_or(binding.count, |count| count.min(binding.count));
+                pool.variable_count = Some(count);
+                count
+            } else {
+                binding.count
+            };
+            if count == 0 {
+                continue;
+            }
+            pool.sizes.push(vk::DescriptorPoolSize {
+                ty: binding.descriptor_type,
+                descriptor_count: count,
+            });
+            pool.mutable_types.push(binding.mutable_types.clone());
+        }
+        pool
+    }
+
+    /// The pools for every set in `builder`.
+    pub fn for_layout(builder: &LayoutBuilder, variable_count: Option<u32>) -> Vec<Self> {
+        builder
+            .sets
+            .iter()
+            .map(|set| Pool::new(set, variable_count))
+            .collect()
+    }
+
+    pub fn descriptor_count(&self) -> u64 {
+        self.sizes
+            .iter()
+            .map(|size| size.descriptor_count as u64)
+            .sum()
+    }
+}
+
+/// Allocated descriptor sets, one pool each so a failure points at the set.
+/// Destroying the pools frees the sets.
+pub struct Descriptors<'a> {
+    pub pools: Vec<Owned<'a, vk::DescriptorPool>>,
+    /// Null for push descriptor sets.
+    pub sets: Vec<vk::DescriptorSet>,
+}
+
+/// Allocates a set of every layout in `layout`, with pools sized by `pools`
+/// (from [`Pool::for_layout`] on the builder `layout` came from).
+pub fn allocate<'a>(
+    device: &'a ash::Device,
+    layout: &Layout,
+    pools: &[Pool],
+) -> Result<Descriptors<'a>> {
+    let mut descriptors = Descriptors {
+        pools: vec![],
+        sets: vec![],
+    };
+    for (set, (set_layout, pool)) in layout.set_layouts.iter().zip(pools).enumerate() {
+        if pool.sizes.is_empty() {
+            descriptors.sets.push(vk::DescriptorSet::null());
+            continue;
+        }
+
+        let type_lists = pool
+            .mutable_types
+            .iter()
+            .map(|types| *vk::MutableDescriptorTypeListVALVE::builder().descriptor_types(types))
+            .collect::<Vec<_>>();
+        let mut mutable_info = vk::MutableDescriptorTypeCreateInfoVALVE::builder()
+            .mutable_descriptor_type_lists(&type_lists);
+        let mut create_info = vk::DescriptorPoolCreateInfo::builder()
+            .flags(pool.flags)
+            .max_sets(1)
+            .pool_sizes(&pool.sizes);
+        if pool.mutable_types.iter().any(|types| !types.is_empty()) {
+            create_info = create_info.push_next(&mut mutable_info);
+        }
+        let descriptor_pool =
+            unsafe { device.create_descriptor_pool(&create_info, memory::callbacks()) }
+                .map_err(|result| Error::Descriptors { set, result })?;
+        let descriptor_pool = Owned::new(
+            device,
+            descriptor_pool,
+            ash::Device::destroy_descriptor_pool,
+        );
+
+        let set_layouts = [**set_layout];
+        let variable_counts = [pool.variable_count.unwrap_or(0)];
+        let mut variable_info = vk::DescriptorSetVariableDescriptorCountAllocateInfo::builder()
+            .descriptor_counts(&variable_counts);
+        let mut allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(*descriptor_pool)
+            .set_layouts(&set_layouts);
+        if pool.variable_count.is_some() {
+            allocate_info = allocate_info.push_next(&mut variable_info);
+        }
+        let sets = unsafe { device.allocate_descriptor_sets(&allocate_info) }
+            .map_err(|result| Error::Descriptors { set, result })?;
+        descriptors.pools.push(descriptor_pool);
+        descriptors.sets.extend(sets);
+    }
+    Ok(descriptors)
+}
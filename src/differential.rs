@@ -0,0 +1,176 @@
+use ash::vk;
+use rand::rngs::StdRng;
+
+use crate::{
+    context::{self, Context},
+    descriptor,
+    error::Result,
+    execute::{Bindings, Executor},
+    identity::Identity,
+    readback::{self, Tolerance},
+    resource::{Element, Spec},
+    LayoutBuilder, PipelineRunner, ShaderLoader,
+};
+
+/// Elements listed per differing resource.
+const LISTED: usize = 8;
+
+/// How to run the shaders on each device.
+pub struct Options {
+    /// Indices into the physical devices in order of preference.
+    pub devices: [usize; 2],
+    pub validation: bool,
+    pub builder: LayoutBuilder,
+    pub limit_policy: descriptor::LimitPolicy,
+    pub variable_count: Option<u32>,
+    pub specs: Vec<Spec>,
+    pub groups: [u32; 3],
+    /// Compile options, as for the other modes.
+    pub stage_flags: vk::PipelineShaderStageCreateFlags,
+    pub subgroup_size: Option<u32>,
+    pub pipeline_flags: vk::PipelineCreateFlags,
+    /// For `f32` and `vec4` contents; `u32` ones are always exact.
+    pub tolerance: Tolerance,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Same,
+    Differs,
+    /// One of the devices can't run the case.
+    Skipped,
+}
+
+/// The outputs of every shader on one device, or `None` if preflight says
+/// to skip the case there.
+fn outputs(
+    identity: &Identity,
+    index: usize,
+    shaders: &[&str],
+    options: &Options,
+    rng: &mut StdRng,
+) -> Result<Option<(String, Vec<readback::Outputs>)>> {
+    let instance = context::Instance::new(identity, options.validation)?;
+    let context = Context::on(instance, index, false)?;
+    let device_identity = context.device_identity();
+    eprintln!(
+        "device {}: {} ({})",
+        index, device_identity.name, device_identity.driver
+    );
+
+    let mut builder = options.builder.clone();
+    match descriptor::preflight(&context, &mut builder, options.limit_policy) {
+        descriptor::Preflight::Fits => {}
+        descriptor::Preflight::Scaled(changes) => {
+            for change in changes {
+                eprintln!("warning: device {}: {}", index, change);
+            }
+        }
+        descriptor::Preflight::Skip(reason) => {
+            eprintln!("skipped: device {}: {}", index, reason);
+            return Ok(None);
+        }
+    }
+    let device = &context.device;
+    let layout = builder.build(device)?;
+    let pools = descriptor::Pool::for_layout(&builder, options.variable_count);
+    let allocated = descriptor::allocate(device, &layout, &pools)?;
+    let runner = PipelineRunner::new(device, *layout.pipeline_layout)
+        .flags(options.pipeline_flags)
+        .stage_flags(options.stage_flags)
+        .subgroup_size(options.subgroup_size);
+    let executor = Executor::new(&context)?;
+    let bindings = Bindings {
+        layout: *layout.pipeline_layout,
+        sets: &allocated.sets,
+        set_layouts: &builder.sets,
+        specs: &options.specs,
+        groups: options.groups,
+    };
+
+    let loader = ShaderLoader::default();
+    let mut outputs = vec![];
+    for shader in shaders {
+        let code = loader.load(shader)?;
+        outputs.push(executor.run(&runner, &bindings, shader, &code, rng)?);
+    }
+    Ok(Some((device_identity.driver, outputs)))
+}
+
+/// The indices of the elements that differ, given the word offsets that do.
+fn elements(offsets: &[usize], element: Element) -> Vec<usize> {
+    let mut elements = offsets
+        .iter()
+        .map(|offset| offset / element.size())
+        .collect::<Vec<_>>();
+    elements.dedup();
+    elements
+}
+
+/// Runs `shaders` (paths) with the same inputs on both devices, one after
+/// the other, and prints every output element that differs, typed as the
+/// resource says.
+pub fn run(
+    identity: &Identity,
+    shaders: &[&str],
+    options: &Options,
+    rng: &mut StdRng,
+) -> Result<Verdict> {
+    // Random contents are made once so both devices see the same.
+    let options = Options {
+        specs: Spec::fixed(&options.specs, rng)?,
+        builder: options.builder.clone(),
+        ..*options
+    };
+
+    let [first, second] = options.devices;
+    let Some((first_driver, first_outputs)) = outputs(identity, first, shaders, &options, rng)?
+    else {
+        return Ok(Verdict::Skipped);
+    };
+    let Some((second_driver, second_outputs)) = outputs(identity, second, shaders, &options, rng)?
+    else {
+        return Ok(Verdict::Skipped);
+    };
+
+    let mut verdict = Verdict::Same;
+    for (shader, (first, second)) in shaders
+        .iter()
+        .zip(first_outputs.iter().zip(&second_outputs))
+    {
+        for ((resource, a), (_, b)) in first.iter().zip(second) {
+            let spec = options
+                .specs
+                .iter()
+                .find(|spec| spec.name == *resource)
+                .unwrap();
+            let tolerance = match spec.element {
+                Element::U32 => Tolerance::Exact,
+                Element::F32 | Element::Vec4 => options.tolerance,
+            };
+            let differing = elements(&readback::differences(a, b, tolerance), spec.element);
+            if differing.is_empty() {
+                continue;
+            }
+            verdict = Verdict::Differs;
+            println!(
+                "{}: {}: {} of {} elements differ",
+                shader,
+                resource,
+                differing.len(),
+                a.len().max(b.len()).div_ceil(spec.element.size())
+            );
+            for &index in differing.iter().take(LISTED) {
+                println!(
+                    "    [{}] {} writes {}, {} writes {}",
+                    index,
+                    first_driver,
+                    spec.element.format(a, index),
+                    second_driver,
+                    spec.element.format(b, index)
+                );
+            }
+        }
+    }
+    Ok(verdict)
+}
@@ -0,0 +1,772 @@
+use std::{fmt, path::Path};
+
+use nom::{IResult, Parser};
+
+use crate::error::{Error, Result};
+
+/// An in-memory driconf file, the format Mesa reads from `drirc.d` and
+/// `DRIRC_CONFIGDIR`.
+///
+/// Every attribute Mesa knows is kept, and so are comments, attached to the
+/// element they precede or, after the last child, to the element they're in,
+/// so a file can be parsed and written back without losing anything Mesa or
+/// a reader would use. Applications come before engines when written.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Driconf {
+    /// Comments before `<driconf>`.
+    pub comments: Vec<String>,
+    pub devices: Vec<Device>,
+    /// Comments after the last device, including any after `</driconf>`.
+    pub trailing_comments: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Device {
+    pub comments: Vec<String>,
+    /// `None` applies to every driver.
+    pub driver: Option<String>,
+    pub device: Option<String>,
+    pub screen: Option<String>,
+    pub kernel_driver: Option<String>,
+    pub applications: Vec<Application>,
+    pub engines: Vec<Engine>,
+    pub trailing_comments: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Application {
+    pub comments: Vec<String>,
+    pub name: String,
+    pub executable: Option<String>,
+    pub executable_regexp: Option<String>,
+    pub sha1: Option<String>,
+    /// Regex against `VkApplicationInfo::pApplicationName`.
+    pub application_name_match: Option<String>,
+    pub application_versions: Option<String>,
+    pub options: Vec<DriOption>,
+    pub trailing_comments: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Engine {
+    pub comments: Vec<String>,
+    /// Regex against `VkApplicationInfo::pEngineName`.
+    pub engine_name_match: String,
+    pub engine_versions: Option<String>,
+    pub options: Vec<DriOption>,
+    pub trailing_comments: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DriOption {
+    pub comments: Vec<String>,
+    pub name: String,
+    pub value: String,
+}
+
+impl Application {
+    /// An application block matching this tool, both by executable name and
+    /// by the `application_name` it passes in `VkApplicationInfo`.
+    pub fn for_tool(application_name: &str, options: Vec<DriOption>) -> Self {
+        let executable = std::env::current_exe()
+            .ok()
+            .and_then(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| env!("CARGO_PKG_NAME").to_string());
+        Application {
+            name: application_name.to_string(),
+            executable: Some(executable),
+            application_name_match: Some(regex_escape(application_name)),
+            options,
+            ..Application::default()
+        }
+    }
+}
+
+impl Driconf {
+    /// Adds `application` to the device block for `driver`. An application
+    /// block for the same executable is merged into option by option: an
+    /// option of the same name gets the new value, others are appended, and
+    /// the block's attributes, other options and comments stay as they are.
+    /// No attribute is added, as Mesa needs every one to match.
+    pub fn merge(&mut self, driver: &str, application: Application) {
+        let device = match self
+            .devices
+            .iter_mut()
+            .position(|device| device.driver.as_deref() == Some(driver))
+        {
+            Some(index) => &mut self.devices[index],
+            None => {
+                self.devices.push(Device {
+                    driver: Some(driver.to_string()),
+                    ..Device::default()
+                });
+                self.devices.last_mut().unwrap()
+            }
+        };
+        match device
+            .applications
+            .iter_mut()
+            .find(|existing| existing.executable == application.executable)
+        {
+            Some(existing) => {
+                for option in application.options {
+                    match existing
+                        .options
+                        .iter_mut()
+                        .find(|existing| existing.name == option.name)
+                    {
+                        Some(existing) => existing.value = option.value,
+                        None => existing.options.push(option),
+                    }
+                }
+            }
+            None => device.applications.push(application),
+        }
+    }
+}
+
+pub fn regex_escape(s: &str) -> String {
+    s.chars()
+        .flat_map(|c| {
+            if "\\.+*?()|[]{}^$".contains(c) {
+                vec!['\\', c]
+            } else {
+                vec![c]
+            }
+        })
+        .collect()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn write_comments(f: &mut fmt::Formatter<'_>, indent: &str, comments: &[String]) -> fmt::Result {
+    for comment in comments {
+        writeln!(f, "{}<!--{}-->", indent, comment)?;
+    }
+    Ok(())
+}
+
+/// Writes ` key="value"` for each attribute that is set.
+fn write_attributes(
+    f: &mut fmt::Formatter<'_>,
+    attributes: &[(&str, &Option<String>)],
+) -> fmt::Result {
+    for (key, value) in attributes {
+        if let Some(value) = value {
+            write!(f, " {}=\"{}\"", key, escape(value))?;
+        }
+    }
+    Ok(())
+}
+
+fn write_options(f: &mut fmt::Formatter<'_>, options: &[DriOption]) -> fmt::Result {
+    for option in options {
+        write_comments(f, "            ", &option.comments)?;
+        writeln!(
+            f,
+            "            <option name=\"{}\" value=\"{}\" />",
+            escape(&option.name),
+            escape(&option.value)
+        )?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Driconf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "<?xml version=\"1.0\" standalone=\"yes\"?>")?;
+        writeln!(f)?;
+        write_comments(f, "", &self.comments)?;
+        writeln!(f, "<driconf>")?;
+        for device in &self.devices {
+            write_comments(f, "    ", &device.comments)?;
+            write!(f, "    <device")?;
+            write_attributes(
+                f,
+                &[
+                    ("driver", &device.driver),
+                    ("device", &device.device),
+                    ("screen", &device.screen),
+                    ("kernel_driver", &device.kernel_driver),
+                ],
+            )?;
+            writeln!(f, ">")?;
+            for application in &device.applications {
+                write_comments(f, "        ", &application.comments)?;
+                write!(
+                    f,
+                    "        <application name=\"{}\"",
+                    escape(&application.name)
+                )?;
+                write_attributes(
+                    f,
+                    &[
+                        ("executable", &application.executable),
+                        ("executable_regexp", &application.executable_regexp),
+                        ("sha1", &application.sha1),
+                        (
+                            "application_name_match",
+                            &application.application_name_match,
+                        ),
+                        ("application_versions", &application.application_versions),
+                    ],
+                )?;
+                writeln!(f, ">")?;
+                write_options(f, &application.options)?;
+                write_comments(f, "            ", &application.trailing_comments)?;
+                writeln!(f, "        </application>")?;
+            }
+            for engine in &device.engines {
+                write_comments(f, "        ", &engine.comments)?;
+                write!(
+                    f,
+                    "        <engine engine_name_match=\"{}\"",
+                    escape(&engine.engine_name_match)
+                )?;
+                write_attributes(f, &[("engine_versions", &engine.engine_versions)])?;
+                writeln!(f, ">")?;
+                write_options(f, &engine.options)?;
+                write_comments(f, "            ", &engine.trailing_comments)?;
+                writeln!(f, "        </engine>")?;
+            }
+            write_comments(f, "        ", &device.trailing_comments)?;
+            writeln!(f, "    </device>")?;
+        }
+        write_comments(f, "    ", &self.trailing_comments)?;
+        writeln!(f, "</driconf>")
+    }
+}
+
+/// A generic XML element; driconf has no text content.
+struct Element<'a> {
+    /// The comments right before the element.
+    comments: Vec<&'a str>,
+    name: &'a str,
+    attributes: Vec<(&'a str, &'a str)>,
+    children: Vec<Element<'a>>,
+    /// The comments after the last child.
+    trailing_comments: Vec<&'a str>,
+    /// Byte offset into the parsed text, for error messages.
+    offset: usize,
+}
+
+/// Whitespace, comments, processing instructions and the doctype, returning
+/// what is inside the comments.
+fn misc(input: &str) -> IResult<&str, Vec<&str>> {
+    use nom::{
+        branch::alt,
+        bytes::complete::{tag, take_until},
+        character::complete::multispace1,
+        combinator::{map, value},
+        multi::many0,
+        sequence::delimited,
+    };
+    map(
+        many0(alt((
+            value(None, multispace1),
+            map(delimited(tag("<!--"), take_until("-->"), tag("-->")), Some),
+            value(None, delimited(tag("<?"), take_until("?>"), tag("?>"))),
+            value(None, delimited(tag("<!DOCTYPE"), take_until(">"), tag(">"))),
+        ))),
+        |comments| comments.into_iter().flatten().collect(),
+    )
+    .parse(input)
+}
+
+fn name(input: &str) -> IResult<&str, &str> {
+    nom::bytes::complete::take_while1(|c: char| c.is_ascii_alphanumeric() || "_-:.".contains(c))
+        .parse(input)
+}
+
+fn attribute(input: &str) -> IResult<&str, (&str, &str)> {
+    use nom::{
+        branch::alt,
+        bytes::complete::take_until,
+        character::complete::{char, multispace0},
+        sequence::{delimited, separated_pair},
+    };
+    separated_pair(
+        name,
+        delimited(multispace0, char('='), multispace0),
+        alt((
+            delimited(char('"'), take_until("\""), char('"')),
+            delimited(char('\''), take_until("'"), char('\'')),
+        )),
+    )
+    .parse(input)
+}
+
+fn element<'a>(full: &'a str) -> impl FnMut(&'a str) -> IResult<&'a str, Element<'a>> {
+    move |input: &'a str| {
+        use nom::{
+            bytes::complete::tag,
+            character::complete::{char, multispace0, multispace1},
+            combinator::verify,
+            multi::many0,
+            sequence::{delimited, pair, preceded, terminated},
+        };
+        let offset = full.len() - input.len();
+        let (input, name_) = preceded(char('<'), name).parse(input)?;
+        let (input, attributes) = many0(preceded(multispace1, attribute)).parse(input)?;
+        let (input, _) = multispace0(input)?;
+        if let Ok((input, _)) = tag::<_, _, nom::error::Error<_>>("/>").parse(input) {
+            return Ok((
+                input,
+                Element {
+                    comments: vec![],
+                    name: name_,
+                    attributes,
+                    children: vec![],
+                    trailing_comments: vec![],
+                    offset,
+                },
+            ));
+        }
+        let (input, _) = char('>')(input)?;
+        let (input, children) = many0(pair(misc, element(full))).parse(input)?;
+        let children = children
+            .into_iter()
+            .map(|(comments, child)| Element { comments, ..child })
+            .collect();
+        let (input, trailing_comments) = terminated(
+            misc,
+            delimited(
+                tag("</"),
+                verify(name, |closing: &str| closing == name_),
+                preceded(multispace0, char('>')),
+            ),
+        )
+        .parse(input)?;
+        Ok((
+            input,
+            Element {
+                comments: vec![],
+                name: name_,
+                attributes,
+                children,
+                trailing_comments,
+                offset,
+            },
+        ))
+    }
+}
+
+fn line(full: &str, offset: usize) -> usize {
+    full[..offset.min(full.len())].matches('\n').count() + 1
+}
+
+/// Checks `element` only has the attributes in `allowed`, returning them
+/// unescaped.
+fn attributes<'a>(
+    full: &str,
+    element: &Element<'a>,
+    allowed: &[&str],
+    errors: &mut Vec<String>,
+) -> Vec<(&'a str, String)> {
+    let mut values = vec![];
+    for &(key, value) in &element.attributes {
+        if !allowed.contains(&key) {
+            errors.push(format!(
+                "line {}: unknown attribute {} on <{}>",
+                line(full, element.offset),
+                key,
+                element.name
+            ));
+        } else if values.iter().any(|(existing, _)| *existing == key) {
+            errors.push(format!(
+                "line {}: duplicate attribute {} on <{}>",
+                line(full, element.offset),
+                key,
+                element.name
+            ));
+        } else {
+            values.push((key, unescape(value)));
+        }
+    }
+    values
+}
+
+fn get(values: &[(&str, String)], key: &str) -> Option<String> {
+    values
+        .iter()
+        .find(|(existing, _)| *existing == key)
+        .map(|(_, value)| value.clone())
+}
+
+fn comments(comments: &[&str]) -> Vec<String> {
+    comments.iter().map(|comment| comment.to_string()).collect()
+}
+
+fn options(full: &str, parent: &Element, errors: &mut Vec<String>) -> Vec<DriOption> {
+    let mut options = vec![];
+    for child in &parent.children {
+        if child.name != "option" {
+            errors.push(format!(
+                "line {}: unexpected <{}> in <{}>",
+                line(full, child.offset),
+                child.name,
+                parent.name
+            ));
+            continue;
+        }
+        if !child.children.is_empty() {
+            errors.push(format!(
+                "line {}: <option> must be empty",
+                line(full, child.offset)
+            ));
+        }
+        let values = attributes(full, child, &["name", "value"], errors);
+        match (get(&values, "name"), get(&values, "value")) {
+            (Some(name), Some(value)) => options.push(DriOption {
+                comments: comments(&child.comments),
+                name,
+                value,
+            }),
+            _ => errors.push(format!(
+                "line {}: <option> needs name and value",
+                line(full, child.offset)
+            )),
+        }
+    }
+    options
+}
+
+/// Parses and validates driconf XML, returning every problem found.
+pub fn parse(text: &str) -> std::result::Result<Driconf, Vec<String>> {
+    use nom::{combinator::eof, sequence::tuple};
+    let (root, after) = match tuple((misc, element(text), misc, eof)).parse(text) {
+        Ok((_, (before, root, after, _))) => (
+            Element {
+                comments: before,
+                ..root
+            },
+            after,
+        ),
+        Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
+            return Err(vec![format!(
+                "line {}: not well-formed XML",
+                line(text, text.len() - e.input.len())
+            )])
+        }
+        Err(nom::Err::Incomplete(_)) => return Err(vec!["truncated XML".to_string()]),
+    };
+
+    let mut errors = vec![];
+    if root.name != "driconf" {
+        errors.push(format!(
+            "line {}: root element is <{}>, expected <driconf>",
+            line(text, root.offset),
+            root.name
+        ));
+    }
+    attributes(text, &root, &[], &mut errors);
+
+    let mut driconf = Driconf {
+        comments: comments(&root.comments),
+        trailing_comments: comments(&[root.trailing_comments.as_slice(), &after].concat()),
+        ..Driconf::default()
+    };
+    for device in &root.children {
+        if device.name != "device" {
+            errors.push(format!(
+                "line {}: unexpected <{}> in <driconf>",
+                line(text, device.offset),
+                device.name
+            ));
+            continue;
+        }
+        let values = attributes(
+            text,
+            device,
+            &["driver", "device", "screen", "kernel_driver"],
+            &mut errors,
+        );
+        let mut parsed = Device {
+            comments: comments(&device.comments),
+            driver: get(&values, "driver"),
+            device: get(&values, "device"),
+            screen: get(&values, "screen"),
+            kernel_driver: get(&values, "kernel_driver"),
+            trailing_comments: comments(&device.trailing_comments),
+            ..Device::default()
+        };
+        for child in &device.children {
+            match child.name {
+                "application" => {
+                    let values = attributes(
+                        text,
+                        child,
+                        &[
+                            "name",
+                            "executable",
+                            "executable_regexp",
+                            "sha1",
+                            "application_name_match",
+                            "application_versions",
+                        ],
+                        &mut errors,
+                    );
+                    let Some(name) = get(&values, "name") else {
+                        errors.push(format!(
+                            "line {}: <application> needs a name",
+                            line(text, child.offset)
+                        ));
+                        continue;
+                    };
+                    if values.iter().all(|(key, _)| *key == "name") {
+                        errors.push(format!(
+                            "line {}: <application name=\"{}\"> matches nothing",
+                            line(text, child.offset),
+                            name
+                        ));
+                    }
+                    parsed.applications.push(Application {
+                        comments: comments(&child.comments),
+                        name,
+                        executable: get(&values, "executable"),
+                        executable_regexp: get(&values, "executable_regexp"),
+                        sha1: get(&values, "sha1"),
+                        application_name_match: get(&values, "application_name_match"),
+                        application_versions: get(&values, "application_versions"),
+                        options: options(text, child, &mut errors),
+                        trailing_comments: comments(&child.trailing_comments),
+                    });
+                }
+                "engine" => {
+                    let values = attributes(
+                        text,
+                        child,
+                        &["engine_name_match", "engine_versions"],
+                        &mut errors,
+                    );
+                    let Some(engine_name_match) = get(&values, "engine_name_match") else {
+                        errors.push(format!(
+                            "line {}: <engine> needs engine_name_match",
+                            line(text, child.offset)
+                        ));
+                        continue;
+                    };
+                    parsed.engines.push(Engine {
+                        comments: comments(&child.comments),
+                        engine_name_match,
+                        engine_versions: get(&values, "engine_versions"),
+                        options: options(text, child, &mut errors),
+                        trailing_comments: comments(&child.trailing_comments),
+                    });
+                }
+                other => errors.push(format!(
+                    "line {}: unexpected <{}> in <device>",
+                    line(text, child.offset),
+                    other
+                )),
+            }
+        }
+        driconf.devices.push(parsed);
+    }
+
+    if errors.is_empty() {
+        Ok(driconf)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Validates the driconf file at `path`, printing every problem. Returns
+/// whether it is valid.
+pub fn check(path: &Path) -> Result<bool> {
+    let text = std::fs::read_to_string(path).map_err(Error::io(path))?;
+    Ok(match parse(&text) {
+        Ok(_) => {
+            println!("{}: ok", path.display());
+            true
+        }
+        Err(errors) => {
+            for error in errors {
+                println!("{}: {}", path.display(), error);
+            }
+            false
+        }
+    })
+}
+
+/// Merges `application` into the driconf at `path` for `driver`, creating the
+/// file if needed. Without a path the block is printed instead. An existing
+/// file that does not validate is left alone.
+pub fn emit(path: Option<&Path>, driver: &str, application: Application) -> Result<()> {
+    let mut driconf = match path {
+        Some(path) => match std::fs::read_to_string(path) {
+            Ok(text) => parse(&text).map_err(|errors| Error::Driconf {
+                path: path.to_path_buf(),
+                errors,
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Driconf::default(),
+            Err(e) => return Err(Error::io(path)(e)),
+        },
+        None => Driconf::default(),
+    };
+    driconf.merge(driver, application);
+    match path {
+        Some(path) => std::fs::write(path, driconf.to_string()).map_err(Error::io(path)),
+        None => {
+            print!("{}", driconf);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = r#"<?xml version="1.0" standalone="yes"?>
+<!DOCTYPE driconf SYSTEM "driconf.dtd">
+<!-- Local overrides. -->
+<driconf>
+    <device driver="anv" kernel_driver="xe" device="0x56a0" screen="0">
+        <!-- The game, however it's launched. -->
+        <application name="Starfield" executable_regexp="Starfield.*\.exe" sha1="abc" application_versions="0:1">
+            <option name="force_vk_vendor" value="-1" />
+            <!-- <option name="fake_sparse" value="true" /> -->
+        </application>
+        <engine engine_name_match="vkd3d" engine_versions="2:">
+            <option name="limit_trig_input_range" value="true" />
+        </engine>
+        <!-- Nothing else yet. -->
+    </device>
+</driconf>
+<!-- End. -->
+"#;
+
+    #[test]
+    fn round_trip_keeps_attributes_and_comments() {
+        let driconf = parse(FILE).unwrap();
+        let device = &driconf.devices[0];
+        assert_eq!(device.kernel_driver.as_deref(), Some("xe"));
+        assert_eq!(device.device.as_deref(), Some("0x56a0"));
+        assert_eq!(device.screen.as_deref(), Some("0"));
+        let application = &device.applications[0];
+        assert_eq!(
+            application.executable_regexp.as_deref(),
+            Some("Starfield.*\\.exe")
+        );
+        assert_eq!(application.sha1.as_deref(), Some("abc"));
+        assert_eq!(application.application_versions.as_deref(), Some("0:1"));
+        assert_eq!(
+            application.trailing_comments,
+            [r#" <option name="fake_sparse" value="true" /> "#]
+        );
+        assert_eq!(driconf.comments, [" Local overrides. "]);
+        assert_eq!(driconf.trailing_comments, [" End. "]);
+
+        let written = driconf.to_string();
+        assert_eq!(parse(&written).unwrap(), driconf);
+        assert!(written.contains("<!-- <option name=\"fake_sparse\""));
+    }
+
+    #[test]
+    fn merge_replaces_only_the_same_executable() {
+        let mut driconf = parse(FILE).unwrap();
+        let option = |value: &str| DriOption {
+            comments: vec![],
+            name: "vk_lower_terminate_to_discard".to_string(),
+            value: value.to_string(),
+        };
+        let application = Application {
+            name: "vk-compute-shader-testing".to_string(),
+            executable: Some("vk-compute-shader-testing".to_string()),
+            ..Application::default()
+        };
+        for value in ["true", "false"] {
+            driconf.merge(
+                "anv",
+                Application {
+                    options: vec![option(value)],
+                    ..application.clone()
+                },
+            );
+        }
+        let applications = &driconf.devices[0].applications;
+        assert_eq!(applications.len(), 2);
+        assert_eq!(applications[1].options, [option("false")]);
+    }
+
+    #[test]
+    fn merge_keeps_the_rest_of_the_block() {
+        let mut driconf = parse(
+            r#"<driconf>
+    <device driver="anv">
+        <application name="Starfield" executable="vk-compute-shader-testing">
+            <!-- option name="force_vk_vendor" value="0x1002" /-->
+            <option name="shader_spilling_rate" value="15" />
+            <option name="vk_lower_terminate_to_discard" value="false" />
+        </application>
+    </device>
+</driconf>
+"#,
+        )
+        .unwrap();
+        let option = |name: &str, value: &str| DriOption {
+            comments: vec![],
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+        driconf.merge(
+            "anv",
+            Application {
+                name: "vk-compute-shader-testing".to_string(),
+                executable: Some("vk-compute-shader-testing".to_string()),
+                application_name_match: Some("vk-compute-shader-testing".to_string()),
+                sha1: Some("abc".to_string()),
+                options: vec![
+                    option("vk_lower_terminate_to_discard", "true"),
+                    option("fake_sparse", "true"),
+                ],
+                ..Application::default()
+            },
+        );
+        let application = &driconf.devices[0].applications[0];
+        assert_eq!(driconf.devices[0].applications.len(), 1);
+        assert_eq!(application.name, "Starfield");
+        // Mesa needs every attribute to match; adding one would narrow it.
+        assert_eq!(application.application_name_match, None);
+        assert_eq!(application.sha1, None);
+        assert_eq!(
+            application.options[0].comments,
+            [r#" option name="force_vk_vendor" value="0x1002" /"#]
+        );
+        assert_eq!(
+            application.options,
+            [
+                DriOption {
+                    comments: application.options[0].comments.clone(),
+                    ..option("shader_spilling_rate", "15")
+                },
+                option("vk_lower_terminate_to_discard", "true"),
+                option("fake_sparse", "true"),
+            ]
+        );
+    }
+
+    #[test]
+    fn problems_are_reported() {
+        let errors = parse(
+            r#"<driconf><device drvier="anv"><application name="x"><option name="a" /></application></device></driconf>"#,
+        )
+        .unwrap_err();
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert!(parse("<driconf><device></driconf>").is_err());
+    }
+}
@@ -0,0 +1,51 @@
+use crate::{
+    case,
+    error::{Error, Result},
+};
+
+/// Variables that change what Mesa and the Vulkan loader do.
+const PREFIXES: [&str; 10] = [
+    "ACO_", "ANV_", "DRIRC_", "GALLIUM_", "INTEL_", "LP_", "MESA_", "NIR_", "RADV_", "VK_",
+];
+
+/// Collects `[env <name>]` sections from the case files, in the order named;
+/// later profiles override earlier ones.
+pub fn profiles(cases: &[case::Case], names: &[String]) -> Result<Vec<(String, String)>> {
+    let mut env: Vec<(String, String)> = vec![];
+    for name in names {
+        let section = cases
+            .iter()
+            .rev()
+            .find_map(|case| case.section("env", name))
+            .ok_or_else(|| Error::Usage(format!("no [env {}] in the case files", name)))?;
+        for (key, value) in &section.entries {
+            env.retain(|(existing, _)| existing != key);
+            env.push((key.clone(), value.clone()));
+        }
+    }
+    Ok(env)
+}
+
+/// The driver relevant part of this process's environment, sorted.
+pub fn effective() -> Vec<(String, String)> {
+    let mut env = std::env::vars()
+        .filter(|(key, _)| PREFIXES.iter().any(|prefix| key.starts_with(prefix)))
+        .collect::<Vec<_>>();
+    env.sort();
+    env
+}
+
+/// [`effective`] without what changes from run to run regardless, for
+/// records that are compared later.
+pub fn recorded() -> Vec<(String, String)> {
+    let mut env = effective();
+    env.retain(|(key, _)| key != "MESA_SHADER_CACHE_DIR");
+    env
+}
+
+/// Prints `env KEY=value` lines that `runner` picks up from children.
+pub fn report() {
+    for (key, value) in effective() {
+        println!("env {}={}", key, value);
+    }
+}
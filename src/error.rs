@@ -0,0 +1,192 @@
+use std::{fmt, path::PathBuf};
+
+use ash::vk;
+
+/// The exit code of a case that was not run because the device can't take
+/// it, the one test harnesses commonly use for skips.
+pub const SKIPPED: u8 = 77;
+
+/// Everything that can stop a run, with enough context to tell which step
+/// failed. Each variant maps to its own exit code so scripts and the sweep
+/// runner can tell failures apart:
+///
+/// | code | meaning                                          |
+/// |------|--------------------------------------------------|
+/// | 0    | success                                          |
+/// | 1    | the run worked but found a problem (regression, invalid driconf) |
+/// | 2    | bad command line or case file                    |
+/// | 3    | Vulkan loader could not be loaded                |
+/// | 4    | instance creation failed                         |
+/// | 5    | no usable physical device or device creation failed |
+/// | 6    | descriptor set layout or pipeline layout creation failed |
+/// | 7    | shader file could not be read                    |
+/// | 8    | shader file is not valid SPIR-V                  |
+/// | 9    | pipeline creation failed                         |
+/// | 10   | another Vulkan call failed                       |
+/// | 11   | reading or writing a file failed                 |
+/// | 12   | a driconf file is invalid                        |
+/// | 13   | descriptor pool creation or set allocation failed |
+/// | 14   | this executable could not be found to run children |
+/// | 77   | case skipped, it needs more than the device allows |
+/// | 101  | panic                                            |
+#[derive(Debug)]
+pub enum Error {
+    Usage(String),
+    Loader(ash::LoadingError),
+    Instance(vk::Result),
+    NoDevice,
+    Device(vk::Result),
+    SetLayout {
+        set: usize,
+        result: vk::Result,
+    },
+    PipelineLayout(vk::Result),
+    ShaderLoad {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    SpirvParse {
+        path: PathBuf,
+        reason: String,
+    },
+    Pipeline {
+        shader: String,
+        result: vk::Result,
+    },
+    Vulkan {
+        call: &'static str,
+        result: vk::Result,
+    },
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Driconf {
+        path: PathBuf,
+        errors: Vec<String>,
+    },
+    Descriptors {
+        set: usize,
+        result: vk::Result,
+    },
+    CurrentExe(std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Usage(_) => 2,
+            Error::Loader(_) => 3,
+            Error::Instance(_) => 4,
+            Error::NoDevice | Error::Device(_) => 5,
+            Error::SetLayout { .. } | Error::PipelineLayout(_) => 6,
+            Error::ShaderLoad { .. } => 7,
+            Error::SpirvParse { .. } => 8,
+            Error::Pipeline { .. } => 9,
+            Error::Vulkan { .. } => 10,
+            Error::Io { .. } => 11,
+            Error::Driconf { .. } => 12,
+            Error::Descriptors { .. } => 13,
+            Error::CurrentExe(_) => 14,
+        }
+    }
+
+    /// The Vulkan result, for errors from a Vulkan call.
+    pub fn result(&self) -> Option<vk::Result> {
+        match self {
+            Error::Instance(result)
+            | Error::Device(result)
+            | Error::SetLayout { result, .. }
+            | Error::Descriptors { result, .. }
+            | Error::PipelineLayout(result)
+            | Error::Pipeline { result, .. }
+            | Error::Vulkan { result, .. } => Some(*result),
+            _ => None,
+        }
+    }
+
+    /// The step behind an exit code, for reporting on child processes.
+    pub fn stage(exit_code: i32) -> Option<&'static str> {
+        Some(match exit_code {
+            1 => "problem found",
+            2 => "usage",
+            3 => "loader",
+            4 => "instance",
+            5 => "device",
+            6 => "layout",
+            7 => "shader load",
+            8 => "SPIR-V parse",
+            9 => "pipeline",
+            10 => "Vulkan call",
+            11 => "file I/O",
+            12 => "driconf",
+            13 => "descriptors",
+            14 => "executable",
+            code if code == SKIPPED as i32 => "skipped",
+            101 => "panic",
+            _ => return None,
+        })
+    }
+
+    /// Wraps an I/O error with the file it happened on.
+    pub fn io(path: impl Into<PathBuf>) -> impl FnOnce(std::io::Error) -> Self {
+        let path = path.into();
+        move |source| Error::Io { path, source }
+    }
+
+    pub fn vulkan(call: &'static str) -> impl FnOnce(vk::Result) -> Self {
+        move |result| Error::Vulkan { call, result }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Usage(message) => write!(f, "{}", message),
+            Error::Loader(e) => write!(f, "loading Vulkan: {}", e),
+            Error::Instance(result) => write!(f, "creating instance: {}", result),
+            Error::NoDevice => write!(f, "no Vulkan physical device"),
+            Error::Device(result) => write!(f, "creating device: {}", result),
+            Error::SetLayout { set, result } => {
+                write!(f, "creating descriptor set layout {}: {}", set, result)
+            }
+            Error::PipelineLayout(result) => write!(f, "creating pipeline layout: {}", result),
+            Error::ShaderLoad { path, source } => {
+                write!(f, "loading shader {}: {}", path.display(), source)
+            }
+            Error::SpirvParse { path, reason } => {
+                write!(f, "parsing SPIR-V in {}: {}", path.display(), reason)
+            }
+            Error::Pipeline { shader, result } => {
+                write!(f, "creating compute pipeline for {}: {}", shader, result)
+            }
+            Error::Vulkan { call, result } => write!(f, "{}: {}", call, result),
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Driconf { path, errors } => {
+                write!(f, "{}: invalid driconf", path.display())?;
+                for error in errors {
+                    write!(f, "\n    {}", error)?;
+                }
+                Ok(())
+            }
+            Error::Descriptors { set, result } => {
+                write!(f, "allocating descriptor set {}: {}", set, result)
+            }
+            Error::CurrentExe(e) => write!(f, "finding this executable: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Loader(e) => Some(e),
+            Error::ShaderLoad { source, .. }
+            | Error::Io { source, .. }
+            | Error::CurrentExe(source) => Some(source),
+            _ => None,
+        }
+    }
+}
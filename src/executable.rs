@@ -0,0 +1,147 @@
+use std::{ffi::CStr, path::Path};
+
+use ash::{extensions::khr, vk};
+
+use crate::error::{Error, Result};
+
+fn string(chars: &[std::ffi::c_char]) -> String {
+    unsafe { CStr::from_ptr(chars.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn format_value(statistic: &vk::PipelineExecutableStatisticKHR) -> String {
+    unsafe {
+        match statistic.format {
+            vk::PipelineExecutableStatisticFormatKHR::BOOL32 => {
+                (statistic.value.b32 != vk::FALSE).to_string()
+            }
+            vk::PipelineExecutableStatisticFormatKHR::INT64 => statistic.value.i64.to_string(),
+            vk::PipelineExecutableStatisticFormatKHR::UINT64 => statistic.value.u64.to_string(),
+            vk::PipelineExecutableStatisticFormatKHR::FLOAT64 => statistic.value.f64.to_string(),
+            format => format!("{:?}", format),
+        }
+    }
+}
+
+/// Prints the statistics of every executable in `pipeline`, which has to be
+/// created with `CAPTURE_STATISTICS_KHR`. With `dump_dir` the internal
+/// representations (`CAPTURE_INTERNAL_REPRESENTATIONS_KHR`) are written to
+/// `<dump_dir>/<shader>-<executable>-<representation>.txt` (`.bin` for
+/// binary ones).
+pub fn report(
+    loader: &khr::PipelineExecutableProperties,
+    pipeline: vk::Pipeline,
+    shader: &str,
+    dump_dir: Option<&Path>,
+) -> Result<()> {
+    let pipeline_info = vk::PipelineInfoKHR::builder().pipeline(pipeline);
+    let executables = unsafe { loader.get_pipeline_executable_properties(&pipeline_info) }
+        .map_err(Error::vulkan("vkGetPipelineExecutablePropertiesKHR"))?;
+
+    for (index, executable) in executables.iter().enumerate() {
+        let name = string(&executable.name);
+        println!(
+            "{}: executable {} {} ({}), subgroup size {}",
+            shader,
+            index,
+            name,
+            string(&executable.description),
+            executable.subgroup_size
+        );
+
+        let executable_info = vk::PipelineExecutableInfoKHR::builder()
+            .pipeline(pipeline)
+            .executable_index(index as u32);
+        for statistic in unsafe { loader.get_pipeline_executable_statistics(&executable_info) }
+            .map_err(Error::vulkan("vkGetPipelineExecutableStatisticsKHR"))?
+        {
+            println!(
+                "    {} = {}",
+                string(&statistic.name),
+                format_value(&statistic)
+            );
+        }
+
+        if let Some(dump_dir) = dump_dir {
+            dump_internal_representations(loader, &executable_info, shader, index, dump_dir)?;
+        }
+    }
+    Ok(())
+}
+
+fn dump_internal_representations(
+    loader: &khr::PipelineExecutableProperties,
+    executable_info: &vk::PipelineExecutableInfoKHR,
+    shader: &str,
+    index: usize,
+    dump_dir: &Path,
+) -> Result<()> {
+    // The loader only fetches the sizes, the data needs a second call with
+    // `p_data` pointing at our buffers.
+    let mut representations =
+        unsafe { loader.get_pipeline_executable_internal_representations(executable_info) }
+            .map_err(Error::vulkan(
+                "vkGetPipelineExecutableInternalRepresentationsKHR",
+            ))?;
+    let mut buffers = representations
+        .iter_mut()
+        .map(|representation| {
+            let mut buffer = vec![0u8; representation.data_size];
+            representation.p_data = buffer.as_mut_ptr().cast();
+            buffer
+        })
+        .collect::<Vec<_>>();
+    let mut count = representations.len() as u32;
+    unsafe {
+        (loader
+            .fp()
+            .get_pipeline_executable_internal_representations_khr)(
+            loader.device(),
+            executable_info,
+            &mut count,
+            representations.as_mut_ptr(),
+        )
+    }
+    .result()
+    .map_err(Error::vulkan(
+        "vkGetPipelineExecutableInternalRepresentationsKHR",
+    ))?;
+
+    std::fs::create_dir_all(dump_dir).map_err(Error::io(dump_dir))?;
+    let stem = Path::new(shader)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| shader.to_string());
+    for (representation, buffer) in representations.iter().zip(buffers.iter_mut()) {
+        let name = string(&representation.name)
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>();
+        buffer.truncate(representation.data_size);
+        if representation.is_text != vk::FALSE {
+            // Text representations include the terminating NUL.
+            while buffer.last() == Some(&0) {
+                buffer.pop();
+            }
+        }
+        let path = dump_dir.join(format!(
+            "{}-{}-{}.{}",
+            stem,
+            index,
+            name,
+            if representation.is_text != vk::FALSE {
+                "txt"
+            } else {
+                "bin"
+            }
+        ));
+        std::fs::write(&path, buffer).map_err(Error::io(&path))?;
+        println!(
+            "    {} written to {}",
+            string(&representation.name),
+            path.display()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,237 @@
+use std::time::{Duration, Instant};
+
+use ash::vk;
+use rand::rngs::StdRng;
+
+use crate::{
+    error::{Error, Result},
+    layout::SetLayout,
+    memory,
+    owned::Owned,
+    readback,
+    resource::{self, Resources, Spec},
+    Context, PipelineRunner,
+};
+
+/// Bytes in the uniform buffer pushed for set 3, the `maxUniformBufferRange`
+/// every device supports.
+pub const UNIFORM_SIZE: vk::DeviceSize = 16384;
+
+/// A buffer descriptor written with `vkCmdPushDescriptorSetKHR` rather than
+/// bound as part of an allocated set.
+#[derive(Clone, Copy, Debug)]
+pub struct Pushed {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    pub buffer_info: vk::DescriptorBufferInfo,
+}
+
+/// What a dispatch of one of the captured shaders gets: the descriptor sets
+/// at their index, null for push descriptor sets, how each is laid out, the
+/// resources to create for it and the workgroup count.
+pub struct Bindings<'b> {
+    pub layout: vk::PipelineLayout,
+    pub sets: &'b [vk::DescriptorSet],
+    pub set_layouts: &'b [SetLayout],
+    pub specs: &'b [Spec],
+    pub groups: [u32; 3],
+}
+
+/// Records and submits dispatches on the device's only queue, one at a time.
+pub struct Executor<'a> {
+    context: &'a Context,
+    queue: vk::Queue,
+    // Destroying the pool frees the command buffer.
+    _command_pool: Owned<'a, vk::CommandPool>,
+    command_buffer: vk::CommandBuffer,
+    fence: Owned<'a, vk::Fence>,
+}
+
+impl<'a> Executor<'a> {
+    pub fn new(context: &'a Context) -> Result<Self> {
+        let device = &context.device;
+        // The device is created with one queue, from family 0.
+        let queue = unsafe { device.get_device_queue(0, 0) };
+
+        let command_pool = unsafe {
+            device.create_command_pool(
+                &vk::CommandPoolCreateInfo::builder()
+                    .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+                    .queue_family_index(0),
+                memory::callbacks(),
+            )
+        }
+        .map_err(Error::vulkan("vkCreateCommandPool"))?;
+        let command_pool = Owned::new(device, command_pool, ash::Device::destroy_command_pool);
+
+        let command_buffer = unsafe {
+            device.allocate_command_buffers(
+                &vk::CommandBufferAllocateInfo::builder()
+                    .command_pool(*command_pool)
+                    .level(vk::CommandBufferLevel::PRIMARY)
+                    .command_buffer_count(1),
+            )
+        }
+        .map_err(Error::vulkan("vkAllocateCommandBuffers"))?[0];
+
+        let fence =
+            unsafe { device.create_fence(&vk::FenceCreateInfo::default(), memory::callbacks()) }
+                .map_err(Error::vulkan("vkCreateFence"))?;
+        let fence = Owned::new(device, fence, ash::Device::destroy_fence);
+
+        Ok(Executor {
+            context,
+            queue,
+            _command_pool: command_pool,
+            command_buffer,
+            fence,
+        })
+    }
+
+    /// Records commands with `record`, submits them and waits. Returns the
+    /// time from submit to the fence signalling.
+    pub fn submit(&self, record: impl FnOnce(&ash::Device, vk::CommandBuffer)) -> Result<Duration> {
+        let device = &self.context.device;
+        let command_buffer = self.command_buffer;
+        unsafe {
+            device
+                .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
+                .map_err(Error::vulkan("vkResetCommandBuffer"))?;
+            device
+                .begin_command_buffer(
+                    command_buffer,
+                    &vk::CommandBufferBeginInfo::builder()
+                        .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+                )
+                .map_err(Error::vulkan("vkBeginCommandBuffer"))?;
+            record(device, command_buffer);
+            device
+                .end_command_buffer(command_buffer)
+                .map_err(Error::vulkan("vkEndCommandBuffer"))?;
+
+            let command_buffers = [command_buffer];
+            let submit = vk::SubmitInfo::builder().command_buffers(&command_buffers);
+            device
+                .reset_fences(&[*self.fence])
+                .map_err(Error::vulkan("vkResetFences"))?;
+            let start = Instant::now();
+            device
+                .queue_submit(self.queue, &[*submit], *self.fence)
+                .map_err(Error::vulkan("vkQueueSubmit"))?;
+            device
+                .wait_for_fences(&[*self.fence], true, u64::MAX)
+                .map_err(Error::vulkan("vkWaitForFences"))?;
+            Ok(start.elapsed())
+        }
+    }
+
+    /// Dispatches `groups` workgroups of `pipeline` with the non-null `sets`
+    /// bound at their index and `pushed` pushed, and waits for it. Shader
+    /// writes are made visible to transfers and the host afterwards.
+    pub fn dispatch(
+        &self,
+        pipeline: vk::Pipeline,
+        layout: vk::PipelineLayout,
+        sets: &[vk::DescriptorSet],
+        pushed: &[Pushed],
+        groups: [u32; 3],
+    ) -> Result<Duration> {
+        self.submit(|device, command_buffer| unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline);
+            for (index, set) in sets.iter().enumerate() {
+                if *set != vk::DescriptorSet::null() {
+                    device.cmd_bind_descriptor_sets(
+                        command_buffer,
+                        vk::PipelineBindPoint::COMPUTE,
+                        layout,
+                        index as u32,
+                        &[*set],
+                        &[],
+                    );
+                }
+            }
+            for push in pushed {
+                let buffer_info = [push.buffer_info];
+                let write = vk::WriteDescriptorSet::builder()
+                    .dst_binding(push.binding)
+                    .descriptor_type(push.descriptor_type)
+                    .buffer_info(&buffer_info);
+                self.context.push_descriptor.cmd_push_descriptor_set(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    layout,
+                    push.set,
+                    &[*write],
+                );
+            }
+            device.cmd_dispatch(command_buffer, groups[0], groups[1], groups[2]);
+
+            let barrier = vk::MemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                .dst_access_mask(vk::AccessFlags::TRANSFER_READ | vk::AccessFlags::HOST_READ);
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[*barrier],
+                &[],
+                &[],
+            );
+        })
+    }
+
+    /// Compiles `code` with `runner`, dispatches it once with fresh resources
+    /// and returns the contents of the writable ones by name.
+    pub fn run(
+        &self,
+        runner: &PipelineRunner,
+        bindings: &Bindings,
+        shader: &str,
+        code: &[u32],
+        rng: &mut StdRng,
+    ) -> Result<readback::Outputs> {
+        let context = self.context;
+        let resources = Resources::new(context, self, bindings.specs, rng)?;
+        for spec in bindings.specs.iter().filter(|spec| spec.device_address) {
+            if let Some(address) = resources.address(&spec.name) {
+                println!("{}: address {:#x}", spec.name, address);
+            }
+        }
+        let mut pushed = resources.bind(&context.device, bindings.sets, bindings.set_layouts)?;
+        // Set 3 gets a zeroed uniform buffer unless the case has one.
+        let uniform =
+            resource::Buffer::new(context, UNIFORM_SIZE, vk::BufferUsageFlags::UNIFORM_BUFFER)?;
+        if !pushed.iter().any(|push| push.set == 3) {
+            pushed.push(Pushed {
+                set: 3,
+                binding: 0,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                buffer_info: uniform.descriptor_info(),
+            });
+        }
+
+        let compilation = runner.compile(code)?;
+        compilation.check(shader)?;
+        let Ok(pipelines) = &compilation.result else {
+            unreachable!()
+        };
+        let groups = bindings.groups;
+        let elapsed = self.dispatch(
+            pipelines[0],
+            bindings.layout,
+            bindings.sets,
+            &pushed,
+            groups,
+        )?;
+        println!(
+            "{}: dispatched {}x{}x{} in {:?}",
+            shader, groups[0], groups[1], groups[2], elapsed
+        );
+        resources
+            .writable()
+            .map(|resource| Ok((resource.spec.name.clone(), resource.read(self)?)))
+            .collect()
+    }
+}
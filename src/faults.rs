@@ -0,0 +1,120 @@
+use std::{fmt, path::Path};
+
+use ash::vk;
+
+use crate::{baseline, error::Result, memory, runner};
+
+/// How the driver dealt with a failed host allocation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// The run finished before the allocation to fail was made.
+    NotReached,
+    /// The failure came back as `VK_ERROR_OUT_OF_HOST_MEMORY`.
+    Clean,
+    /// The driver worked around the failure and the compile passed.
+    Recovered,
+    /// The failure came back as some other error.
+    OtherError(String),
+    /// The driver crashed, or the tool panicked.
+    Crash,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verdict::NotReached => write!(f, "not reached"),
+            Verdict::Clean => write!(f, "clean"),
+            Verdict::Recovered => write!(f, "recovered"),
+            Verdict::OtherError(result) => write!(f, "returned {}", result),
+            Verdict::Crash => write!(f, "crash"),
+        }
+    }
+}
+
+fn verdict(run: &runner::Run) -> Verdict {
+    let out_of_memory = format!("{:?}", vk::Result::ERROR_OUT_OF_HOST_MEMORY);
+    match &run.outcome {
+        runner::Outcome::Crashed(_) | runner::Outcome::Failed(Some(101)) => Verdict::Crash,
+        _ if run.faults.is_empty() => Verdict::NotReached,
+        runner::Outcome::Skipped => Verdict::OtherError("skipped".to_string()),
+        runner::Outcome::Completed(records) => {
+            match records.first().map(|record| &record.outcome) {
+                Some(baseline::Outcome::Fail(vk::Result::ERROR_OUT_OF_HOST_MEMORY)) => {
+                    Verdict::Clean
+                }
+                Some(baseline::Outcome::Fail(result)) => {
+                    Verdict::OtherError(format!("{:?}", result))
+                }
+                _ => Verdict::Recovered,
+            }
+        }
+        runner::Outcome::Failed(_) => match &run.result {
+            Some(result) if *result == out_of_memory => Verdict::Clean,
+            Some(result) => Verdict::OtherError(result.clone()),
+            None => Verdict::OtherError("no Vulkan result".to_string()),
+        },
+    }
+}
+
+/// The command line options that make a child fail allocations as `fault`.
+pub fn options(fault: &memory::Fault) -> Vec<String> {
+    let mut args = vec![];
+    if let Some(nth) = fault.nth {
+        args.push(format!("--fail-alloc={}", nth));
+    }
+    if let Some(above) = fault.above {
+        args.push(format!("--fail-alloc-above={}", above));
+    }
+    args
+}
+
+/// Compiles each of `shaders` (indices into `SHADERS`) in a child process per
+/// fault and prints how each ended. Faults are tried in order; a shader stops
+/// at the first one its run does not reach, since later allocations would not
+/// be reached either.
+pub fn run(
+    shaders: &[usize],
+    faults: &[memory::Fault],
+    args: &[String],
+    env: &[(String, String)],
+    shader_cache: Option<&Path>,
+) -> Result<()> {
+    let mut rows = vec![];
+    for &shader in shaders {
+        for fault in faults {
+            let fault_args = options(fault);
+            let mut child_args = vec![format!("-{}", shader + 1)];
+            child_args.extend_from_slice(args);
+            child_args.extend_from_slice(&fault_args);
+            let run = runner::run(&child_args, env, shader_cache)?;
+            let verdict = verdict(&run);
+            eprintln!(
+                "{} {}: {}, {}",
+                shader + 1,
+                fault_args.join(" "),
+                run.outcome,
+                verdict
+            );
+            for fault in &run.faults {
+                eprintln!("    {}", fault);
+            }
+            rows.push(vec![
+                (shader + 1).to_string(),
+                fault_args.join(" "),
+                run.faults
+                    .first()
+                    .cloned()
+                    .unwrap_or_else(|| "-".to_string()),
+                run.outcome.to_string(),
+                verdict.to_string(),
+            ]);
+            if verdict == Verdict::NotReached {
+                break;
+            }
+        }
+    }
+
+    let header = ["shader", "fault", "failed allocation", "outcome", "verdict"].map(str::to_string);
+    runner::print_table(&header, &rows);
+    Ok(())
+}
@@ -0,0 +1,283 @@
+use std::path::Path;
+
+use ash::vk;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng};
+
+use crate::{
+    error::Result,
+    layout::{Binding, LayoutBuilder, MUTABLE_TYPES},
+    runner,
+};
+
+/// What a non-mutable binding can be turned into.
+const TYPES: [vk::DescriptorType; 7] = [
+    vk::DescriptorType::SAMPLER,
+    vk::DescriptorType::SAMPLED_IMAGE,
+    vk::DescriptorType::STORAGE_IMAGE,
+    vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+    vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+    vk::DescriptorType::UNIFORM_BUFFER,
+    vk::DescriptorType::STORAGE_BUFFER,
+];
+
+/// The types update after bind can be turned on for. `Context` only enables
+/// `descriptorBindingSampledImageUpdateAfterBind`, which covers these.
+const UPDATE_AFTER_BIND_TYPES: [vk::DescriptorType; 3] = [
+    vk::DescriptorType::SAMPLER,
+    vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+    vk::DescriptorType::SAMPLED_IMAGE,
+];
+
+/// Whether the enabled features allow update after bind on `binding`; a
+/// mutable one needs it for every type it can hold.
+fn update_after_bind_allowed(binding: &Binding) -> bool {
+    match binding.descriptor_type {
+        vk::DescriptorType::MUTABLE_VALVE => binding
+            .mutable_types
+            .iter()
+            .all(|descriptor_type| UPDATE_AFTER_BIND_TYPES.contains(descriptor_type)),
+        descriptor_type => UPDATE_AFTER_BIND_TYPES.contains(&descriptor_type),
+    }
+}
+
+/// Turns update after bind off on `binding` if its type no longer allows it.
+fn drop_update_after_bind(binding: &mut Binding, at: &str, mutations: &mut Vec<String>) {
+    if binding
+        .flags
+        .contains(vk::DescriptorBindingFlags::UPDATE_AFTER_BIND)
+        && !update_after_bind_allowed(binding)
+    {
+        binding.flags &= !vk::DescriptorBindingFlags::UPDATE_AFTER_BIND;
+        mutations.push(format!("{}: UPDATE_AFTER_BIND off", at));
+    }
+}
+
+/// Binding flags that are toggled; the variable count and update after bind
+/// ones only where the layout and the enabled features allow them.
+const FLAGS: [vk::DescriptorBindingFlags; 4] = [
+    vk::DescriptorBindingFlags::PARTIALLY_BOUND,
+    vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING,
+    vk::DescriptorBindingFlags::UPDATE_AFTER_BIND,
+    vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT,
+];
+
+/// Applies one to four random changes to `builder`: a binding count, a
+/// binding flag, a descriptor type, a mutable type list or the stage mask.
+/// Push descriptor sets are left alone, as what gets pushed is fixed. Counts
+/// are not kept within the device limits here; preflight scales them
+/// afterwards like for any layout. Returns a line per change.
+pub fn mutate(builder: &mut LayoutBuilder, rng: &mut StdRng) -> Vec<String> {
+    let mut mutations = vec![];
+    for _ in 0..rng.gen_range(1..=4) {
+        let bindings = builder
+            .sets
+            .iter()
+            .enumerate()
+            .filter(|(_, layout)| {
+                !layout
+                    .flags
+                    .contains(vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR)
+            })
+            .flat_map(|(set, layout)| (0..layout.bindings.len()).map(move |index| (set, index)))
+            .collect::<Vec<_>>();
+        let Some(&(set, index)) = bindings.choose(rng) else {
+            break;
+        };
+        let set_flags = builder.sets[set].flags;
+        let last = index + 1 == builder.sets[set].bindings.len();
+        let binding = &mut builder.sets[set].bindings[index];
+        let at = format!("set {} binding {}", set, index);
+
+        match rng.gen_range(0..5) {
+            0 => {
+                let count = ((binding.count as f64) * 2f64.powf(rng.gen_range(-4.0..=2.0)))
+                    .clamp(1., u32::MAX as f64) as u32;
+                mutations.push(format!("{}: count {} -> {}", at, binding.count, count));
+                binding.count = count;
+            }
+            1 => {
+                let flag = *FLAGS.choose(rng).unwrap();
+                let allowed = match flag {
+                    vk::DescriptorBindingFlags::UPDATE_AFTER_BIND => {
+                        binding.flags.contains(flag)
+                            || set_flags.contains(
+                                vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL,
+                            ) && update_after_bind_allowed(binding)
+                    }
+                    vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT => last,
+                    _ => true,
+                };
+                if allowed {
+                    binding.flags ^= flag;
+                    mutations.push(format!(
+                        "{}: {:?} {}",
+                        at,
+                        flag,
+                        if binding.flags.contains(flag) {
+                            "on"
+                        } else {
+                            "off"
+                        }
+                    ));
+                }
+            }
+            2 if binding.descriptor_type != vk::DescriptorType::MUTABLE_VALVE => {
+                let descriptor_type = *TYPES.choose(rng).unwrap();
+                mutations.push(format!(
+                    "{}: {:?} -> {:?}",
+                    at, binding.descriptor_type, descriptor_type
+                ));
+                binding.descriptor_type = descriptor_type;
+                drop_update_after_bind(binding, &at, &mut mutations);
+            }
+            2 | 3 if binding.descriptor_type == vk::DescriptorType::MUTABLE_VALVE => {
+                let count = rng.gen_range(1..=MUTABLE_TYPES.len());
+                binding.mutable_types =
+                    MUTABLE_TYPES.choose_multiple(rng, count).copied().collect();
+                mutations.push(format!("{}: mutable types {:?}", at, binding.mutable_types));
+                drop_update_after_bind(binding, &at, &mut mutations);
+            }
+            4 => {
+                // Always including compute, or the shaders can't see any of it.
+                let stage_flags = *[
+                    LayoutBuilder::default().stage_flags,
+                    vk::ShaderStageFlags::ALL,
+                    vk::ShaderStageFlags::COMPUTE,
+                    vk::ShaderStageFlags::COMPUTE | vk::ShaderStageFlags::FRAGMENT,
+                ]
+                .choose(rng)
+                .unwrap();
+                mutations.push(format!(
+                    "stage flags {:?} -> {:?}",
+                    builder.stage_flags, stage_flags
+                ));
+                builder.stage_flags = stage_flags;
+            }
+            _ => {}
+        }
+    }
+    mutations
+}
+
+/// Whether a fuzzed run turned something up.
+fn problem(run: &runner::Run) -> Option<String> {
+    match &run.outcome {
+        runner::Outcome::Crashed(_) | runner::Outcome::Failed(Some(101)) => {
+            Some(run.outcome.to_string())
+        }
+        _ if !run.validation.is_empty() => {
+            Some(format!("{} validation errors", run.validation.len()))
+        }
+        _ => None,
+    }
+}
+
+/// Compiles `shader` (an index into `SHADERS`) against `runs` mutated
+/// layouts, each in a child process with its own seed drawn from `rng`, and
+/// prints every run that crashed or made the validation layer complain,
+/// with the seed to reproduce it. Returns whether any did.
+pub fn run(
+    shader: usize,
+    runs: usize,
+    args: &[String],
+    env: &[(String, String)],
+    shader_cache: Option<&Path>,
+    rng: &mut StdRng,
+) -> Result<bool> {
+    let mut rows = vec![];
+    let mut found = vec![];
+    for _ in 0..runs {
+        let seed = rng.gen::<u64>();
+        let mut child_args = vec![format!("-{}", shader + 1)];
+        child_args.extend_from_slice(args);
+        child_args.push("--fuzz-layout".to_string());
+        child_args.push(format!("--seed={}", seed));
+        let run = runner::run(&child_args, env, shader_cache)?;
+        let problem = problem(&run);
+        rows.push(vec![
+            seed.to_string(),
+            run.mutations.len().to_string(),
+            run.outcome.to_string(),
+            problem.clone().unwrap_or_else(|| "-".to_string()),
+        ]);
+        if problem.is_some() {
+            found.push((seed, run));
+        }
+    }
+
+    let header = ["seed", "mutations", "outcome", "problem"].map(str::to_string);
+    runner::print_table(&header, &rows);
+    for (seed, run) in &found {
+        println!(
+            "seed {}: {}, rerun with -{} --fuzz-layout --seed={}",
+            seed,
+            run.outcome,
+            shader + 1,
+            seed
+        );
+        for mutation in &run.mutations {
+            println!("    {}", mutation);
+        }
+        for message in &run.validation {
+            println!("    {}", message);
+        }
+    }
+    Ok(!found.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn mutations_stay_valid() {
+        let captured = LayoutBuilder::captured().push_descriptors(3);
+        for seed in 0..500 {
+            let mut builder = captured.clone();
+            mutate(&mut builder, &mut StdRng::seed_from_u64(seed));
+            // The push descriptor set is left alone.
+            assert_eq!(
+                format!("{:?}", builder.sets[3]),
+                format!("{:?}", captured.sets[3]),
+                "seed {}",
+                seed
+            );
+            for (set, (before, after)) in captured.sets.iter().zip(&builder.sets).enumerate() {
+                let last = after.bindings.len() - 1;
+                for (index, (before, after)) in
+                    before.bindings.iter().zip(&after.bindings).enumerate()
+                {
+                    let changed = after.descriptor_type != before.descriptor_type
+                        || after.mutable_types != before.mutable_types
+                        || !before
+                            .flags
+                            .contains(vk::DescriptorBindingFlags::UPDATE_AFTER_BIND);
+                    if changed
+                        && after
+                            .flags
+                            .contains(vk::DescriptorBindingFlags::UPDATE_AFTER_BIND)
+                    {
+                        assert!(
+                            update_after_bind_allowed(after),
+                            "seed {} set {} binding {}",
+                            seed,
+                            set,
+                            index
+                        );
+                    }
+                    if index != last
+                        && !before
+                            .flags
+                            .contains(vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT)
+                    {
+                        assert!(!after
+                            .flags
+                            .contains(vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT));
+                    }
+                }
+            }
+        }
+    }
+}
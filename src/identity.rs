@@ -0,0 +1,113 @@
+use ash::vk;
+use nom::Parser;
+
+/// What the tool reports about itself in `VkApplicationInfo`. Drivers key
+/// workarounds on these, so spoofing a game's identity can reproduce behavior
+/// that otherwise only shows up in the game.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Identity {
+    pub application_name: String,
+    pub application_version: u32,
+    pub engine_name: String,
+    pub engine_version: u32,
+    pub api_version: u32,
+}
+
+impl Default for Identity {
+    fn default() -> Self {
+        Identity {
+            application_name: crate::APPLICATION_NAME.to_string(),
+            application_version: vk::make_api_version(0, 0, 0, 1),
+            engine_name: crate::APPLICATION_NAME.to_string(),
+            engine_version: vk::make_api_version(0, 0, 0, 1),
+            api_version: vk::API_VERSION_1_3,
+        }
+    }
+}
+
+impl Identity {
+    /// Named identities for `--preset=`.
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Identity::default()),
+            "starfield" => Some(Identity {
+                application_name: "Starfield".to_string(),
+                engine_name: "CreationEngine".to_string(),
+                ..Identity::default()
+            }),
+            // What the game looks like to the driver when running through
+            // Proton.
+            "vkd3d-starfield" => Some(Identity {
+                application_name: "Starfield.exe".to_string(),
+                engine_name: "vkd3d".to_string(),
+                ..Identity::default()
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Parses `major.minor[.patch]` into a Vulkan packed version.
+pub fn parse_version(input: &str) -> Option<u32> {
+    use nom::{
+        character::complete::{char, u32},
+        combinator::{all_consuming, opt},
+        error::Error,
+        sequence::{preceded, tuple},
+    };
+    let (_, (major, minor, patch)) = all_consuming(tuple((
+        u32::<_, Error<_>>,
+        preceded(char('.'), u32),
+        opt(preceded(char('.'), u32)),
+    )))
+    .parse(input)
+    .ok()?;
+    (major < 128 && minor < 1024 && patch.unwrap_or(0) < 4096)
+        .then(|| vk::make_api_version(0, major, minor, patch.unwrap_or(0)))
+}
+
+pub fn format_version(version: u32) -> String {
+    format!(
+        "{}.{}.{}",
+        vk::api_version_major(version),
+        vk::api_version_minor(version),
+        vk::api_version_patch(version)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_parse_and_format() {
+        assert_eq!(parse_version("1.3"), Some(vk::API_VERSION_1_3));
+        assert_eq!(
+            parse_version("1.2.250"),
+            Some(vk::make_api_version(0, 1, 2, 250))
+        );
+        assert_eq!(
+            format_version(parse_version("127.1023.4095").unwrap()),
+            "127.1023.4095"
+        );
+    }
+
+    #[test]
+    fn bad_versions_are_rejected() {
+        for input in [
+            "", "1", "1.", "1.3.", "1.3.0.0", "v1.3", "128.0", "1.1024", "1.0.4096",
+        ] {
+            assert_eq!(parse_version(input), None, "{}", input);
+        }
+    }
+
+    #[test]
+    fn presets() {
+        assert_eq!(Identity::preset("default"), Some(Identity::default()));
+        assert_eq!(
+            Identity::preset("starfield").unwrap().application_name,
+            "Starfield"
+        );
+        assert_eq!(Identity::preset("unknown"), None);
+    }
+}
@@ -0,0 +1,444 @@
+use ash::vk;
+
+use crate::{
+    error::{Error, Result},
+    memory,
+    owned::Owned,
+};
+
+/// What a `MUTABLE_VALVE` binding can hold, as captured.
+pub const MUTABLE_TYPES: [vk::DescriptorType; 5] = [
+    vk::DescriptorType::STORAGE_BUFFER,
+    vk::DescriptorType::SAMPLED_IMAGE,
+    vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+    vk::DescriptorType::STORAGE_IMAGE,
+    vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+];
+
+/// One binding of a descriptor set layout; bindings are numbered in order.
+#[derive(Clone, Debug)]
+pub struct Binding {
+    pub descriptor_type: vk::DescriptorType,
+    pub count: u32,
+    pub flags: vk::DescriptorBindingFlags,
+    /// What a `MUTABLE_VALVE` binding can hold.
+    pub mutable_types: Vec<vk::DescriptorType>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SetLayout {
+    pub flags: vk::DescriptorSetLayoutCreateFlags,
+    pub bindings: Vec<Binding>,
+}
+
+impl SetLayout {
+    /// The binding whose count is only an upper bound, chosen when a set is
+    /// allocated. Only the last binding can be one.
+    pub fn variable_binding(&self) -> Option<usize> {
+        let last = self.bindings.last()?;
+        last.flags
+            .contains(vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT)
+            .then(|| self.bindings.len() - 1)
+    }
+}
+
+/// Created descriptor set layouts and the pipeline layout over them. The
+/// pipeline layout is destroyed first.
+pub struct Layout<'a> {
+    pub pipeline_layout: Owned<'a, vk::PipelineLayout>,
+    pub set_layouts: Vec<Owned<'a, vk::DescriptorSetLayout>>,
+}
+
+/// Describes a pipeline layout set by set, every binding visible to
+/// `stage_flags`.
+#[derive(Clone, Debug)]
+pub struct LayoutBuilder {
+    pub stage_flags: vk::ShaderStageFlags,
+    pub sets: Vec<SetLayout>,
+}
+
+impl Default for LayoutBuilder {
+    fn default() -> Self {
+        let template0 = vk::ShaderStageFlags::empty()
+            | vk::ShaderStageFlags::VERTEX
+            | vk::ShaderStageFlags::TESSELLATION_CONTROL
+            | vk::ShaderStageFlags::TESSELLATION_EVALUATION
+            | vk::ShaderStageFlags::GEOMETRY
+            | vk::ShaderStageFlags::FRAGMENT
+            | vk::ShaderStageFlags::COMPUTE
+            | vk::ShaderStageFlags::ALL
+            | vk::ShaderStageFlags::RAYGEN_KHR
+            | vk::ShaderStageFlags::ANY_HIT_KHR
+            | vk::ShaderStageFlags::CLOSEST_HIT_KHR
+            | vk::ShaderStageFlags::MISS_KHR
+            | vk::ShaderStageFlags::INTERSECTION_KHR
+            | vk::ShaderStageFlags::CALLABLE_KHR
+            | vk::ShaderStageFlags::TASK_NV
+            | vk::ShaderStageFlags::MESH_NV
+            | vk::ShaderStageFlags::SUBPASS_SHADING_HUAWEI
+            | vk::ShaderStageFlags::empty();
+        LayoutBuilder {
+            stage_flags: template0,
+            sets: vec![],
+        }
+    }
+}
+
+impl LayoutBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts the next set.
+    pub fn set(mut self, flags: vk::DescriptorSetLayoutCreateFlags) -> Self {
+        self.sets.push(SetLayout {
+            flags,
+            bindings: vec![],
+        });
+        self
+    }
+
+    /// Adds a binding to the current set, starting set 0 if there is none.
+    pub fn binding(
+        mut self,
+        descriptor_type: vk::DescriptorType,
+        count: u32,
+        flags: vk::DescriptorBindingFlags,
+    ) -> Self {
+        if self.sets.is_empty() {
+            self.sets.push(SetLayout::default());
+        }
+        self.sets.last_mut().unwrap().bindings.push(Binding {
+            descriptor_type,
+            count,
+            flags,
+            mutable_types: vec![],
+        });
+        self
+    }
+
+    /// Sets the types the last binding, a `MUTABLE_VALVE` one, can hold.
+    pub fn mutable_types(mut self, types: &[vk::DescriptorType]) -> Self {
+        if let Some(binding) = self.sets.last_mut().and_then(|set| set.bindings.last_mut()) {
+            binding.mutable_types = types.to_vec();
+        }
+        self
+    }
+
+    /// Makes `set` a push descriptor set, as the game has set 3. Push
+    /// descriptor sets can't be update after bind, so that flag goes.
+    pub fn push_descriptors(mut self, set: usize) -> Self {
+        self.sets[set].flags = vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR;
+        self
+    }
+
+    /// Makes the layout one sets can be allocated from, which the captured
+    /// one isn't: only the last binding of a set keeps a variable count, and
+    /// `MUTABLE_VALVE` bindings without a type list get `MUTABLE_TYPES`.
+    pub fn allocatable(mut self) -> Self {
+        for set in &mut self.sets {
+            let last = set.bindings.len().saturating_sub(1);
+            for (index, binding) in set.bindings.iter_mut().enumerate() {
+                if index != last {
+                    binding.flags &= !vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT;
+                }
+                if binding.descriptor_type == vk::DescriptorType::MUTABLE_VALVE
+                    && binding.mutable_types.is_empty()
+                {
+                    binding.mutable_types = MUTABLE_TYPES.to_vec();
+                }
+            }
+        }
+        self
+    }
+
+    /// The layout the captured shaders were compiled against.
+    pub fn captured() -> Self {
+        // pSetLayouts[0]:                 const VkDescriptorSetLayout = 0x7e511920
+        // pSetLayouts[1]:                 const VkDescriptorSetLayout = 0x7de00d60
+        // pSetLayouts[2]:                 const VkDescriptorSetLayout = 0x7f5930001430
+        // pSetLayouts[3]:                 const VkDescriptorSetLayout = 0x7f5930189b70
+
+        let bindless = vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+            | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING
+            | vk::DescriptorBindingFlags::PARTIALLY_BOUND
+            | vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT;
+        let update_after_bind = vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL;
+
+        let mut builder = LayoutBuilder::new();
+
+        /*
+                               flags:                          VkDescriptorSetLayoutCreateFlags = 2 (VK_DESCRIPTOR_SET_LAYOUT_CREATE_UPDATE_AFTER_BIND_POOL_BIT)
+               bindingCount:                   uint32_t = 1
+               pBindings:                      const VkDescriptorSetLayoutBinding* = 0x11e6c0
+                   pBindings[0]:                   const VkDescriptorSetLayoutBinding = 0x11e6c0:
+                       binding:                        uint32_t = 0
+                       descriptorType:                 VkDescriptorType = VK_DESCRIPTOR_TYPE_SAMPLER (0)
+                       descriptorCount:                uint32_t = 2048
+                       stageFlags:                     VkShaderStageFlags = 2147483647 template0
+                       pImmutableSamplers:             const VkSampler* = NULL
+               pNext:                          VkDescriptorSetLayoutBindingFlagsCreateInfo = 0x11e560:
+                   sType:                          VkStructureType = VK_STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_BINDING_FLAGS_CREATE_INFO (1000161000)
+                   pNext:                          const void* = NULL
+                   bindingCount:                   uint32_t = 1
+                   pBindingFlags:                  const VkDescriptorBindingFlags* = 0x11e600
+                       pBindingFlags[0]:               const VkDescriptorBindingFlags = 15 (VK_DESCRIPTOR_BINDING_UPDATE_AFTER_BIND_BIT
+        VK_DESCRIPTOR_BINDING_UPDATE_UNUSED_WHILE_PENDING_BIT
+        VK_DESCRIPTOR_BINDING_PARTIALLY_BOUND_BIT
+        VK_DESCRIPTOR_BINDING_VARIABLE_DESCRIPTOR_COUNT_BIT)
+                */
+        builder =
+            builder
+                .set(update_after_bind)
+                .binding(vk::DescriptorType::SAMPLER, 2048, bindless);
+
+        /*
+                flags:                          VkDescriptorSetLayoutCreateFlags = 2 (VK_DESCRIPTOR_SET_LAYOUT_CREATE_UPDATE_AFTER_BIND_POOL_BIT)
+                bindingCount:                   uint32_t = 3
+                pBindings:                      const VkDescriptorSetLayoutBinding* = 0x11e6c0
+                    pBindings[0]:                   const VkDescriptorSetLayoutBinding = 0x11e6c0:
+                        binding:                        uint32_t = 0
+                        descriptorType:                 VkDescriptorType = VK_DESCRIPTOR_TYPE_STORAGE_BUFFER (7)
+                        descriptorCount:                uint32_t = 1
+                        stageFlags:                     VkShaderStageFlags = 2147483647 template0
+                        pImmutableSamplers:             const VkSampler* = UNUSED
+                    pBindings[1]:                   const VkDescriptorSetLayoutBinding = 0x11e6d8:
+                        binding:                        uint32_t = 1
+                        descriptorType:                 VkDescriptorType = VK_DESCRIPTOR_TYPE_STORAGE_BUFFER (7)
+                        descriptorCount:                uint32_t = 1
+                        stageFlags:                     VkShaderStageFlags = 2147483647 template0
+                        pImmutableSamplers:             const VkSampler* = UNUSED
+                    pBindings[2]:                   const VkDescriptorSetLayoutBinding = 0x11e6f0:
+                        binding:                        uint32_t = 2
+                        descriptorType:                 VkDescriptorType = VK_DESCRIPTOR_TYPE_MUTABLE_VALVE (1000351000)
+                        descriptorCount:                uint32_t = 1000000
+                        stageFlags:                     VkShaderStageFlags = 2147483647 template0
+                        pImmutableSamplers:             const VkSampler* = UNUSED
+                pNext:                          VkDescriptorSetLayoutBindingFlagsCreateInfo = 0x11e560:
+                    sType:                          VkStructureType = VK_STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_BINDING_FLAGS_CREATE_INFO (1000161000)
+                    pNext:                          const void* = VkMutableDescriptorTypeCreateInfoVALVE
+                    bindingCount:                   uint32_t = 3
+                    pBindingFlags:                  const VkDescriptorBindingFlags* = 0x11e600
+                        pBindingFlags[0]:               const VkDescriptorBindingFlags = 0
+                        pBindingFlags[1]:               const VkDescriptorBindingFlags = 0
+                        pBindingFlags[2]:               const VkDescriptorBindingFlags = 15 (VK_DESCRIPTOR_BINDING_UPDATE_AFTER_BIND_BIT
+         VK_DESCRIPTOR_BINDING_UPDATE_UNUSED_WHILE_PENDING_BIT
+         VK_DESCRIPTOR_BINDING_PARTIALLY_BOUND_BIT
+         VK_DESCRIPTOR_BINDING_VARIABLE_DESCRIPTOR_COUNT_BIT)
+                pNext:                          VkMutableDescriptorTypeCreateInfoVALVE = 0x11e5e0:
+                    sType:                          VkStructureType = VK_STRUCTURE_TYPE_MUTABLE_DESCRIPTOR_TYPE_CREATE_INFO_VALVE (1000351002)
+                    pNext:                          const void* = NULL
+                    mutableDescriptorTypeListCount: uint32_t = 3
+                    pMutableDescriptorTypeLists:    const VkMutableDescriptorTypeListVALVE* = 0x11e630
+                        pMutableDescriptorTypeLists[0]: const VkMutableDescriptorTypeListVALVE = 0x11e630:
+                            descriptorTypeCount:            uint32_t = 0
+                            pDescriptorTypes:               const VkDescriptorType* = NULL
+                        pMutableDescriptorTypeLists[1]: const VkMutableDescriptorTypeListVALVE = 0x11e640:
+                            descriptorTypeCount:            uint32_t = 0
+                            pDescriptorTypes:               const VkDescriptorType* = NULL
+                        pMutableDescriptorTypeLists[2]: const VkMutableDescriptorTypeListVALVE = 0x11e650:
+                            descriptorTypeCount:            uint32_t = 5
+                            pDescriptorTypes:               const VkDescriptorType* = 0x11e540
+                                pDescriptorTypes[0]:            const VkDescriptorType = VK_DESCRIPTOR_TYPE_STORAGE_BUFFER (7)
+                                pDescriptorTypes[1]:            const VkDescriptorType = VK_DESCRIPTOR_TYPE_SAMPLED_IMAGE (2)
+                                pDescriptorTypes[2]:            const VkDescriptorType = VK_DESCRIPTOR_TYPE_UNIFORM_TEXEL_BUFFER (4)
+                                pDescriptorTypes[3]:            const VkDescriptorType = VK_DESCRIPTOR_TYPE_STORAGE_IMAGE (3)
+                                pDescriptorTypes[4]:            const VkDescriptorType = VK_DESCRIPTOR_TYPE_STORAGE_TEXEL_BUFFER (5)
+        */
+        builder = builder
+            .set(update_after_bind)
+            .binding(vk::DescriptorType::STORAGE_BUFFER, 1, bindless)
+            .binding(vk::DescriptorType::STORAGE_BUFFER, 1, bindless)
+            .binding(vk::DescriptorType::MUTABLE_VALVE, 1000000, bindless);
+
+        /*
+                flags:                          VkDescriptorSetLayoutCreateFlags = 0
+                bindingCount:                   uint32_t = 10
+                pBindings:                      const VkDescriptorSetLayoutBinding* = 0xd25de0
+                    pBindings[0]:                   const VkDescriptorSetLayoutBinding = 0xd25de0:
+                        binding:                        uint32_t = 0
+                        descriptorType:                 VkDescriptorType = VK_DESCRIPTOR_TYPE_SAMPLER (0)
+                        descriptorCount:                uint32_t = 1
+                        stageFlags:                     VkShaderStageFlags = 2147483647 template0
+                        pImmutableSamplers:             const VkSampler* = 0xcb4fd0
+                            pImmutableSamplers[0]:          const VkSampler = 0x7f5930000b70
+                    pBindings[1]:                   const VkDescriptorSetLayoutBinding = 0xd25df8:
+                        binding:                        uint32_t = 1
+                        descriptorType:                 VkDescriptorType = VK_DESCRIPTOR_TYPE_SAMPLER (0)
+                        descriptorCount:                uint32_t = 1
+                        stageFlags:                     VkShaderStageFlags = 2147483647 template0
+                        pImmutableSamplers:             const VkSampler* = 0xcb4fd8
+                            pImmutableSamplers[0]:          const VkSampler = 0x7f5930000c50
+                    pBindings[2]:                   const VkDescriptorSetLayoutBinding = 0xd25e10:
+                        binding:                        uint32_t = 2
+                        descriptorType:                 VkDescriptorType = VK_DESCRIPTOR_TYPE_SAMPLER (0)
+                        descriptorCount:                uint32_t = 1
+                        stageFlags:                     VkShaderStageFlags = 2147483647 template0
+                        pImmutableSamplers:             const VkSampler* = 0xcb4fe0
+                            pImmutableSamplers[0]:          const VkSampler = 0x7f5930000d30
+                    pBindings[3]:                   const VkDescriptorSetLayoutBinding = 0xd25e28:
+                        binding:                        uint32_t = 3
+                        descriptorType:                 VkDescriptorType = VK_DESCRIPTOR_TYPE_SAMPLER (0)
+                        descriptorCount:                uint32_t = 1
+                        stageFlags:                     VkShaderStageFlags = 2147483647 template0
+                        pImmutableSamplers:             const VkSampler* = 0xcb4fe8
+                            pImmutableSamplers[0]:          const VkSampler = 0x7f5930000e10
+                    pBindings[4]:                   const VkDescriptorSetLayoutBinding = 0xd25e40:
+                        binding:                        uint32_t = 4
+                        descriptorType:                 VkDescriptorType = VK_DESCRIPTOR_TYPE_SAMPLER (0)
+                        descriptorCount:                uint32_t = 1
+                        stageFlags:                     VkShaderStageFlags = 2147483647 template0
+                        pImmutableSamplers:             const VkSampler* = 0xcb4ff0
+                            pImmutableSamplers[0]:          const VkSampler = 0x7f5930000ef0
+                    pBindings[5]:                   const VkDescriptorSetLayoutBinding = 0xd25e58:
+                        binding:                        uint32_t = 5
+                        descriptorType:                 VkDescriptorType = VK_DESCRIPTOR_TYPE_SAMPLER (0)
+                        descriptorCount:                uint32_t = 1
+                        stageFlags:                     VkShaderStageFlags = 2147483647 template0
+                        pImmutableSamplers:             const VkSampler* = 0xcb4ff8
+                            pImmutableSamplers[0]:          const VkSampler = 0x7f5930000fd0
+                    pBindings[6]:                   const VkDescriptorSetLayoutBinding = 0xd25e70:
+                        binding:                        uint32_t = 6
+                        descriptorType:                 VkDescriptorType = VK_DESCRIPTOR_TYPE_SAMPLER (0)
+                        descriptorCount:                uint32_t = 1
+                        stageFlags:                     VkShaderStageFlags = 2147483647 template0
+                        pImmutableSamplers:             const VkSampler* = 0xcb5000
+                            pImmutableSamplers[0]:          const VkSampler = 0x7f59300010b0
+                    pBindings[7]:                   const VkDescriptorSetLayoutBinding = 0xd25e88:
+                        binding:                        uint32_t = 7
+                        descriptorType:                 VkDescriptorType = VK_DESCRIPTOR_TYPE_SAMPLER (0)
+                        descriptorCount:                uint32_t = 1
+                        stageFlags:                     VkShaderStageFlags = 2147483647 template0
+                        pImmutableSamplers:             const VkSampler* = 0xcb5008
+                            pImmutableSamplers[0]:          const VkSampler = 0x7f5930001190
+                    pBindings[8]:                   const VkDescriptorSetLayoutBinding = 0xd25ea0:
+                        binding:                        uint32_t = 8
+                        descriptorType:                 VkDescriptorType = VK_DESCRIPTOR_TYPE_SAMPLER (0)
+                        descriptorCount:                uint32_t = 1
+                        stageFlags:                     VkShaderStageFlags = 2147483647 template0
+                        pImmutableSamplers:             const VkSampler* = 0xcb5010
+                            pImmutableSamplers[0]:          const VkSampler = 0x7f5930001270
+                    pBindings[9]:                   const VkDescriptorSetLayoutBinding = 0xd25eb8:
+                        binding:                        uint32_t = 9
+                        descriptorType:                 VkDescriptorType = VK_DESCRIPTOR_TYPE_SAMPLER (0)
+                        descriptorCount:                uint32_t = 1
+                        stageFlags:                     VkShaderStageFlags = 2147483647 template0
+                        pImmutableSamplers:             const VkSampler* = 0xcb5018
+                            pImmutableSamplers[0]:          const VkSampler = 0x7f5930001350
+        */
+        builder = builder.set(update_after_bind);
+        for _ in 0..10 {
+            builder = builder.binding(vk::DescriptorType::SAMPLER, 1, bindless);
+        }
+
+        /*
+               flags:                          VkDescriptorSetLayoutCreateFlags = 1 (VK_DESCRIPTOR_SET_LAYOUT_CREATE_PUSH_DESCRIPTOR_BIT_KHR)
+               bindingCount:                   uint32_t = 1
+               pBindings:                      const VkDescriptorSetLayoutBinding* = 0xa6c410
+                   pBindings[0]:                   const VkDescriptorSetLayoutBinding = 0xa6c410:
+                       binding:                        uint32_t = 0
+                       descriptorType:                 VkDescriptorType = VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER (6)
+                       descriptorCount:                uint32_t = 1
+                       stageFlags:                     VkShaderStageFlags = 2147483647 template0
+                       pImmutableSamplers:             const VkSampler* = UNUSED
+        */
+        builder.set(update_after_bind).binding(
+            vk::DescriptorType::UNIFORM_BUFFER,
+            1,
+            vk::DescriptorBindingFlags::empty(),
+        )
+    }
+
+    /// Calls `f` with the create info for `set`.
+    pub fn with_create_info<R>(
+        &self,
+        set: usize,
+        f: impl FnOnce(&vk::DescriptorSetLayoutCreateInfo) -> R,
+    ) -> R {
+        let layout = &self.sets[set];
+        let bindings = layout
+            .bindings
+            .iter()
+            .enumerate()
+            .map(|(binding, description)| {
+                *vk::DescriptorSetLayoutBinding::builder()
+                    .binding(binding as u32)
+                    .descriptor_count(description.count)
+                    .descriptor_type(description.descriptor_type)
+                    .stage_flags(self.stage_flags)
+            })
+            .collect::<Vec<_>>();
+        let binding_flags = layout
+            .bindings
+            .iter()
+            .map(|binding| binding.flags)
+            .collect::<Vec<_>>();
+        let mut flags_info =
+            vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder().binding_flags(&binding_flags);
+        let type_lists = layout
+            .bindings
+            .iter()
+            .map(|binding| {
+                *vk::MutableDescriptorTypeListVALVE::builder()
+                    .descriptor_types(&binding.mutable_types)
+            })
+            .collect::<Vec<_>>();
+        let mut mutable_info = vk::MutableDescriptorTypeCreateInfoVALVE::builder()
+            .mutable_descriptor_type_lists(&type_lists);
+        let mut create_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .flags(layout.flags)
+            .bindings(&bindings);
+        if binding_flags.iter().any(|flags| !flags.is_empty()) {
+            create_info = create_info.push_next(&mut flags_info);
+        }
+        if layout
+            .bindings
+            .iter()
+            .any(|binding| !binding.mutable_types.is_empty())
+        {
+            create_info = create_info.push_next(&mut mutable_info);
+        }
+        f(&create_info)
+    }
+
+    /// Creates the layouts. Whatever was created before a failure is
+    /// destroyed again.
+    pub fn build<'a>(&self, device: &'a ash::Device) -> Result<Layout<'a>> {
+        let set_layouts = (0..self.sets.len())
+            .map(|set| {
+                let set_layout = self
+                    .with_create_info(set, |create_info| unsafe {
+                        device.create_descriptor_set_layout(create_info, memory::callbacks())
+                    })
+                    .map_err(|result| Error::SetLayout { set, result })?;
+                Ok(Owned::new(
+                    device,
+                    set_layout,
+                    ash::Device::destroy_descriptor_set_layout,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let handles = set_layouts
+            .iter()
+            .map(|set_layout| **set_layout)
+            .collect::<Vec<_>>();
+        let pipeline_layout = unsafe {
+            device.create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo::builder().set_layouts(&handles),
+                memory::callbacks(),
+            )
+        }
+        .map_err(Error::PipelineLayout)?;
+        let pipeline_layout = Owned::new(
+            device,
+            pipeline_layout,
+            ash::Device::destroy_pipeline_layout,
+        );
+
+        Ok(Layout {
+            set_layouts,
+            pipeline_layout,
+        })
+    }
+}
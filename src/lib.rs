@@ -0,0 +1,42 @@
+//! Harness for reproducing compute pipeline compiles outside the game that
+//! triggered them: the instance and device setup, the captured descriptor set
+//! layouts and the shader compile loop, usable from other tools and tests.
+//! The `vk-compute-shader-testing` binary is a command line over this.
+
+pub mod baseline;
+pub mod bench;
+pub mod cache;
+pub mod case;
+pub mod context;
+pub mod descriptor;
+pub mod differential;
+pub mod driconf;
+pub mod env;
+pub mod error;
+pub mod executable;
+pub mod execute;
+pub mod faults;
+pub mod fuzz;
+pub mod identity;
+pub mod layout;
+pub mod matrix;
+pub mod memory;
+pub mod modes;
+pub mod options;
+pub mod owned;
+pub mod pipeline;
+pub mod readback;
+pub mod resource;
+pub mod runner;
+pub mod shader;
+pub mod sweep;
+
+pub use context::Context;
+pub use layout::LayoutBuilder;
+pub use pipeline::PipelineRunner;
+pub use shader::ShaderLoader;
+
+pub const APPLICATION_NAME: &str = "Compute Shader Testing";
+
+/// The captured shaders, relative to the crate root.
+pub const SHADERS: [&str; 3] = ["data/150-0.bin", "data/151-0.bin", "data/152-0.bin"];
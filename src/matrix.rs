@@ -0,0 +1,327 @@
+use std::{ops::BitOr, path::Path};
+
+use ash::vk;
+
+use crate::{context::SubgroupSupport, error::Result, runner, ShaderLoader, SHADERS};
+
+/// Stage create flags the matrix toggles, by their `--stage-flags=` names.
+pub const STAGE_FLAGS: [(&str, vk::PipelineShaderStageCreateFlags); 2] = [
+    (
+        "require-full-subgroups",
+        vk::PipelineShaderStageCreateFlags::REQUIRE_FULL_SUBGROUPS,
+    ),
+    (
+        "allow-varying-subgroup-size",
+        vk::PipelineShaderStageCreateFlags::ALLOW_VARYING_SUBGROUP_SIZE,
+    ),
+];
+
+/// Pipeline create flags the matrix toggles, by their `--pipeline-flags=`
+/// names.
+pub const PIPELINE_FLAGS: [(&str, vk::PipelineCreateFlags); 2] = [
+    (
+        "disable-optimization",
+        vk::PipelineCreateFlags::DISABLE_OPTIMIZATION,
+    ),
+    (
+        "fail-on-compile-required",
+        vk::PipelineCreateFlags::FAIL_ON_PIPELINE_COMPILE_REQUIRED,
+    ),
+];
+
+/// Subgroup sizes tried when `--subgroup-sizes=` isn't given, besides none.
+pub const SUBGROUP_SIZES: [u32; 3] = [8, 16, 32];
+
+/// Parses a comma separated list of names from `table`, or `none`.
+pub fn parse<T: Copy + Default + BitOr<Output = T>>(table: &[(&str, T)], value: &str) -> Option<T> {
+    if value == "none" {
+        return Some(T::default());
+    }
+    value.split(',').try_fold(T::default(), |flags, name| {
+        let (_, flag) = table.iter().find(|(known, _)| *known == name)?;
+        Some(flags | *flag)
+    })
+}
+
+/// Every subset of the names in `table`, as option values.
+fn subsets<T>(table: &[(&str, T)]) -> Vec<String> {
+    (0..1usize << table.len())
+        .map(|mask| {
+            let names = table
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| mask & (1 << index) != 0)
+                .map(|(_, (name, _))| *name)
+                .collect::<Vec<_>>();
+            if names.is_empty() {
+                "none".to_string()
+            } else {
+                names.join(",")
+            }
+        })
+        .collect()
+}
+
+/// Why `support` or the spec rules out compiling a shader `local_size_x`
+/// wide, if known, with `stage_flags`, a required subgroup `size` and
+/// `pipeline_flags`.
+fn unsupported(
+    support: &SubgroupSupport,
+    local_size_x: Option<u32>,
+    stage_flags: vk::PipelineShaderStageCreateFlags,
+    size: Option<u32>,
+    pipeline_flags: vk::PipelineCreateFlags,
+) -> Option<String> {
+    if stage_flags.contains(vk::PipelineShaderStageCreateFlags::REQUIRE_FULL_SUBGROUPS)
+        && !support.compute_full_subgroups
+    {
+        return Some("no computeFullSubgroups".to_string());
+    }
+    if stage_flags.contains(vk::PipelineShaderStageCreateFlags::ALLOW_VARYING_SUBGROUP_SIZE)
+        && !support.subgroup_size_control
+    {
+        return Some("no subgroupSizeControl".to_string());
+    }
+    if pipeline_flags.contains(vk::PipelineCreateFlags::FAIL_ON_PIPELINE_COMPILE_REQUIRED)
+        && !support.pipeline_creation_cache_control
+    {
+        return Some("no pipelineCreationCacheControl".to_string());
+    }
+    let size = size?;
+    if stage_flags.contains(vk::PipelineShaderStageCreateFlags::ALLOW_VARYING_SUBGROUP_SIZE) {
+        // VUID-VkPipelineShaderStageCreateInfo-pNext-02754
+        Some("varying subgroup size with a required one".to_string())
+    } else if !support.subgroup_size_control {
+        Some("no subgroupSizeControl".to_string())
+    } else if !support
+        .required_subgroup_size_stages
+        .contains(vk::ShaderStageFlags::COMPUTE)
+    {
+        Some("compute not in requiredSubgroupSizeStages".to_string())
+    } else if !(support.min_subgroup_size..=support.max_subgroup_size).contains(&size) {
+        Some(format!(
+            "subgroup size outside {}..={}",
+            support.min_subgroup_size, support.max_subgroup_size
+        ))
+    } else {
+        match local_size_x {
+            // Full subgroups of a required size have to tile the workgroup.
+            Some(x)
+                if stage_flags
+                    .contains(vk::PipelineShaderStageCreateFlags::REQUIRE_FULL_SUBGROUPS)
+                    && x % size != 0 =>
+            {
+                Some(format!("local size x {} not a multiple of {}", x, size))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Compiles each of `shaders` (indices into `SHADERS`) in a child process for
+/// every combination of stage flags, required subgroup size (none, then each
+/// of `subgroup_sizes`) and pipeline flags, and prints a table of how each
+/// ended, to find which combination a driver bug needs. Combinations
+/// `support` or the spec rules out are marked unsupported instead of run.
+/// Where a shader's local size can't be read, full subgroups with a
+/// required size are run unchecked, and the row says so.
+pub fn run(
+    shaders: &[usize],
+    subgroup_sizes: &[u32],
+    support: &SubgroupSupport,
+    args: &[String],
+    env: &[(String, String)],
+    shader_cache: Option<&Path>,
+) -> Result<()> {
+    let sizes = std::iter::once(None)
+        .chain(subgroup_sizes.iter().copied().map(Some))
+        .collect::<Vec<_>>();
+    let mut rows = vec![];
+    for &shader in shaders {
+        let local_size_x = ShaderLoader::default()
+            .load(SHADERS[shader])
+            .ok()
+            .and_then(|code| ShaderLoader::local_size(&code))
+            .map(|[x, _, _]| x);
+        for stage_flags in subsets(&STAGE_FLAGS) {
+            for size in &sizes {
+                for pipeline_flags in subsets(&PIPELINE_FLAGS) {
+                    let flags = parse(&STAGE_FLAGS, &stage_flags).unwrap();
+                    let reason = unsupported(
+                        support,
+                        local_size_x,
+                        flags,
+                        *size,
+                        parse(&PIPELINE_FLAGS, &pipeline_flags).unwrap(),
+                    );
+                    let unchecked = local_size_x.is_none()
+                        && size.is_some()
+                        && flags
+                            .contains(vk::PipelineShaderStageCreateFlags::REQUIRE_FULL_SUBGROUPS);
+                    let outcome = match reason {
+                        Some(reason) => format!("unsupported ({})", reason),
+                        None => {
+                            let mut child_args = vec![format!("-{}", shader + 1)];
+                            child_args.extend_from_slice(args);
+                            child_args.push(format!("--stage-flags={}", stage_flags));
+                            child_args.extend(size.map(|size| format!("--subgroup-size={}", size)));
+                            child_args.push(format!("--pipeline-flags={}", pipeline_flags));
+                            let outcome = runner::run(&child_args, env, shader_cache)?.outcome;
+                            if unchecked {
+                                format!("{} (local size not checked)", outcome)
+                            } else {
+                                outcome.to_string()
+                            }
+                        }
+                    };
+                    let size = size.map_or_else(|| "-".to_string(), |size| size.to_string());
+                    eprintln!(
+                        "{} {} {} {}: {}",
+                        shader + 1,
+                        stage_flags,
+                        size,
+                        pipeline_flags,
+                        outcome
+                    );
+                    rows.push(vec![
+                        (shader + 1).to_string(),
+                        stage_flags.clone(),
+                        size,
+                        pipeline_flags,
+                        outcome,
+                    ]);
+                }
+            }
+        }
+    }
+
+    let header = [
+        "shader",
+        "stage flags",
+        "subgroup size",
+        "pipeline flags",
+        "outcome",
+    ]
+    .map(str::to_string);
+    runner::print_table(&header, &rows);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full_support() -> SubgroupSupport {
+        SubgroupSupport {
+            vulkan13: true,
+            subgroup_size_control: true,
+            compute_full_subgroups: true,
+            pipeline_creation_cache_control: true,
+            min_subgroup_size: 8,
+            max_subgroup_size: 32,
+            required_subgroup_size_stages: vk::ShaderStageFlags::COMPUTE,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn flags_parse() {
+        assert_eq!(
+            parse(&STAGE_FLAGS, "none"),
+            Some(vk::PipelineShaderStageCreateFlags::empty())
+        );
+        assert_eq!(
+            parse(&STAGE_FLAGS, "allow-varying-subgroup-size"),
+            Some(vk::PipelineShaderStageCreateFlags::ALLOW_VARYING_SUBGROUP_SIZE)
+        );
+        assert_eq!(
+            parse(
+                &PIPELINE_FLAGS,
+                "fail-on-compile-required,disable-optimization"
+            ),
+            Some(
+                vk::PipelineCreateFlags::DISABLE_OPTIMIZATION
+                    | vk::PipelineCreateFlags::FAIL_ON_PIPELINE_COMPILE_REQUIRED
+            )
+        );
+        for input in [
+            "",
+            "bogus",
+            "disable-optimization,",
+            "none,disable-optimization",
+        ] {
+            assert_eq!(parse(&PIPELINE_FLAGS, input), None, "{}", input);
+        }
+    }
+
+    #[test]
+    fn subsets_cover_every_combination() {
+        assert_eq!(
+            subsets(&STAGE_FLAGS),
+            [
+                "none",
+                "require-full-subgroups",
+                "allow-varying-subgroup-size",
+                "require-full-subgroups,allow-varying-subgroup-size",
+            ]
+        );
+        assert_eq!(subsets::<u32>(&[]), ["none"]);
+        for subset in subsets(&PIPELINE_FLAGS) {
+            assert!(parse(&PIPELINE_FLAGS, &subset).is_some(), "{}", subset);
+        }
+    }
+
+    #[test]
+    fn unsupported_combinations() {
+        let none = vk::PipelineShaderStageCreateFlags::empty();
+        let full = vk::PipelineShaderStageCreateFlags::REQUIRE_FULL_SUBGROUPS;
+        let varying = vk::PipelineShaderStageCreateFlags::ALLOW_VARYING_SUBGROUP_SIZE;
+        let no_pipeline_flags = vk::PipelineCreateFlags::empty();
+        let fail_on_compile = vk::PipelineCreateFlags::FAIL_ON_PIPELINE_COMPILE_REQUIRED;
+
+        let support = full_support();
+        let check = |stage_flags, size, pipeline_flags| {
+            unsupported(&support, Some(64), stage_flags, size, pipeline_flags)
+        };
+        assert_eq!(check(none, None, no_pipeline_flags), None);
+        assert_eq!(check(full | varying, None, fail_on_compile), None);
+        assert_eq!(check(full, Some(32), fail_on_compile), None);
+        // A required size rules out a varying one.
+        assert!(check(varying, Some(32), no_pipeline_flags).is_some());
+        assert!(check(full | varying, Some(32), no_pipeline_flags).is_some());
+        assert!(check(none, Some(4), no_pipeline_flags).is_some());
+        assert!(check(none, Some(64), no_pipeline_flags).is_some());
+
+        // Full subgroups of the required size have to tile the local size,
+        // when it is known.
+        assert!(unsupported(&support, Some(24), full, Some(16), no_pipeline_flags).is_some());
+        assert_eq!(
+            unsupported(&support, Some(24), none, Some(16), no_pipeline_flags),
+            None
+        );
+        assert_eq!(
+            unsupported(&support, None, full, Some(16), no_pipeline_flags),
+            None
+        );
+
+        let support = SubgroupSupport::default();
+        let check = |stage_flags, size, pipeline_flags| {
+            unsupported(&support, Some(64), stage_flags, size, pipeline_flags)
+        };
+        assert_eq!(check(none, None, no_pipeline_flags), None);
+        assert!(check(full, None, no_pipeline_flags).is_some());
+        assert!(check(varying, None, no_pipeline_flags).is_some());
+        assert!(check(none, None, fail_on_compile).is_some());
+        assert!(check(none, Some(16), no_pipeline_flags).is_some());
+        assert_eq!(
+            check(none, None, vk::PipelineCreateFlags::DISABLE_OPTIMIZATION),
+            None
+        );
+
+        let support = SubgroupSupport {
+            required_subgroup_size_stages: vk::ShaderStageFlags::FRAGMENT,
+            ..full_support()
+        };
+        assert!(unsupported(&support, Some(64), none, Some(16), no_pipeline_flags).is_some());
+    }
+}
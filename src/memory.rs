@@ -0,0 +1,324 @@
+use std::{
+    alloc::Layout,
+    collections::BTreeMap,
+    ffi::c_void,
+    ptr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, MutexGuard,
+    },
+};
+
+use ash::vk;
+
+/// `VkSystemAllocationScope` values in order, as printed.
+const SCOPES: [&str; 5] = ["command", "object", "cache", "device", "instance"];
+
+/// Host memory of one allocation scope, in bytes.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScopeUsage {
+    pub current: usize,
+    pub peak: usize,
+}
+
+/// Host memory the driver asked for, by allocation scope. Internal
+/// allocations the driver only notifies about are included.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Usage {
+    pub scopes: [ScopeUsage; 5],
+}
+
+/// Which allocations to fail. Reallocations that grow count as allocations.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Fault {
+    /// Fail the Nth allocation of the process, counting from 1.
+    pub nth: Option<u64>,
+    /// Fail every allocation larger than this many bytes.
+    pub above: Option<usize>,
+}
+
+/// An allocation that was failed on purpose.
+#[derive(Clone, Copy, Debug)]
+pub struct Injection {
+    pub allocation: u64,
+    pub size: usize,
+    pub scope: &'static str,
+}
+
+struct State {
+    usage: Usage,
+    allocations: BTreeMap<usize, (Layout, usize)>,
+    count: u64,
+    fault: Fault,
+    injected: Vec<Injection>,
+}
+
+impl State {
+    /// Counts an allocation and decides whether it fails.
+    fn inject(&mut self, size: usize, scope: usize) -> bool {
+        self.count += 1;
+        let fail = self.fault.nth == Some(self.count)
+            || self.fault.above.is_some_and(|above| size > above);
+        if fail {
+            self.injected.push(Injection {
+                allocation: self.count,
+                size,
+                scope: SCOPES[scope],
+            });
+        }
+        fail
+    }
+
+    fn add(&mut self, scope: usize, size: usize) {
+        let usage = &mut self.usage.scopes[scope];
+        usage.current += size;
+        usage.peak = usage.peak.max(usage.current);
+    }
+
+    fn remove(&mut self, scope: usize, size: usize) {
+        let usage = &mut self.usage.scopes[scope];
+        usage.current = usage.current.saturating_sub(size);
+    }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static STATE: Mutex<State> = Mutex::new(State {
+    usage: Usage {
+        scopes: [ScopeUsage {
+            current: 0,
+            peak: 0,
+        }; 5],
+    },
+    allocations: BTreeMap::new(),
+    count: 0,
+    fault: Fault {
+        nth: None,
+        above: None,
+    },
+    injected: Vec::new(),
+});
+
+// A panic can't unwind out of the callbacks, so a poisoned lock is used as is.
+fn state() -> MutexGuard<'static, State> {
+    STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn scope_index(scope: vk::SystemAllocationScope) -> usize {
+    (scope.as_raw() as usize).min(SCOPES.len() - 1)
+}
+
+unsafe extern "system" fn allocation(
+    _: *mut c_void,
+    size: usize,
+    alignment: usize,
+    scope: vk::SystemAllocationScope,
+) -> *mut c_void {
+    let Ok(layout) = Layout::from_size_align(size.max(1), alignment) else {
+        return ptr::null_mut();
+    };
+    let mut state = state();
+    if state.inject(size, scope_index(scope)) {
+        return ptr::null_mut();
+    }
+    let memory = std::alloc::alloc(layout);
+    if !memory.is_null() {
+        state.add(scope_index(scope), layout.size());
+        state
+            .allocations
+            .insert(memory as usize, (layout, scope_index(scope)));
+    }
+    memory.cast()
+}
+
+unsafe extern "system" fn reallocation(
+    user_data: *mut c_void,
+    original: *mut c_void,
+    size: usize,
+    alignment: usize,
+    scope: vk::SystemAllocationScope,
+) -> *mut c_void {
+    if original.is_null() {
+        return allocation(user_data, size, alignment, scope);
+    }
+    if size == 0 {
+        free(user_data, original);
+        return ptr::null_mut();
+    }
+    let mut state = state();
+    let Some((layout, old_scope)) = state.allocations.remove(&(original as usize)) else {
+        return ptr::null_mut();
+    };
+    if size > layout.size() && state.inject(size, scope_index(scope)) {
+        state
+            .allocations
+            .insert(original as usize, (layout, old_scope));
+        return ptr::null_mut();
+    }
+    let memory = std::alloc::realloc(original.cast(), layout, size);
+    if memory.is_null() {
+        // The original stays valid.
+        state
+            .allocations
+            .insert(original as usize, (layout, old_scope));
+        return ptr::null_mut();
+    }
+    let new_layout = Layout::from_size_align_unchecked(size, layout.align());
+    state.remove(old_scope, layout.size());
+    state.add(scope_index(scope), size);
+    state
+        .allocations
+        .insert(memory as usize, (new_layout, scope_index(scope)));
+    memory.cast()
+}
+
+unsafe extern "system" fn free(_: *mut c_void, memory: *mut c_void) {
+    if memory.is_null() {
+        return;
+    }
+    let mut state = state();
+    if let Some((layout, scope)) = state.allocations.remove(&(memory as usize)) {
+        state.remove(scope, layout.size());
+        std::alloc::dealloc(memory.cast(), layout);
+    }
+}
+
+unsafe extern "system" fn internal_allocation(
+    _: *mut c_void,
+    size: usize,
+    _: vk::InternalAllocationType,
+    scope: vk::SystemAllocationScope,
+) {
+    state().add(scope_index(scope), size);
+}
+
+unsafe extern "system" fn internal_free(
+    _: *mut c_void,
+    size: usize,
+    _: vk::InternalAllocationType,
+    scope: vk::SystemAllocationScope,
+) {
+    state().remove(scope_index(scope), size);
+}
+
+struct Callbacks(vk::AllocationCallbacks);
+
+// The callbacks only touch the global state, behind its lock.
+unsafe impl Sync for Callbacks {}
+
+static CALLBACKS: Callbacks = Callbacks(vk::AllocationCallbacks {
+    p_user_data: ptr::null_mut(),
+    pfn_allocation: Some(allocation),
+    pfn_reallocation: Some(reallocation),
+    pfn_free: Some(free),
+    pfn_internal_allocation: Some(internal_allocation),
+    pfn_internal_free: Some(internal_free),
+});
+
+/// Turns on tracking. Has to happen before the instance is created, since
+/// objects must be destroyed with the callbacks they were created with.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Fails allocations as `fault` says from now on, and turns on tracking
+/// since that is what routes allocations through here.
+pub fn inject(fault: Fault) {
+    state().fault = fault;
+    enable();
+}
+
+/// The allocations failed so far.
+pub fn injected() -> Vec<Injection> {
+    state().injected.clone()
+}
+
+/// What to pass as `pAllocator` to every create and destroy call.
+pub fn callbacks() -> Option<&'static vk::AllocationCallbacks> {
+    ENABLED.load(Ordering::Relaxed).then_some(&CALLBACKS.0)
+}
+
+pub fn usage() -> Usage {
+    state().usage
+}
+
+/// Starts a new peak for every scope from the current usage.
+pub fn reset_peaks() {
+    for usage in &mut state().usage.scopes {
+        usage.peak = usage.current;
+    }
+}
+
+/// The process's peak resident set size (`VmHWM`) in bytes.
+pub fn peak_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))?;
+    let kib = line.trim().strip_suffix("kB")?.trim().parse::<u64>().ok()?;
+    Some(kib * 1024)
+}
+
+/// Resets `VmHWM` to the current RSS. Returns whether that worked; without it
+/// the peak covers the whole process lifetime.
+pub fn reset_peak_rss() -> bool {
+    std::fs::write("/proc/self/clear_refs", "5").is_ok()
+}
+
+/// Usage at the start of a measured stretch, normally one compile.
+pub struct Mark {
+    before: Usage,
+    rss_reset: bool,
+}
+
+impl Mark {
+    pub fn new() -> Self {
+        reset_peaks();
+        Mark {
+            before: usage(),
+            rss_reset: reset_peak_rss(),
+        }
+    }
+
+    /// Prints peak RSS and, per scope that saw any allocations, the peak and
+    /// the bytes still outstanding, both relative to the mark. Objects should be
+    /// destroyed first so outstanding bytes are leaks.
+    pub fn report(&self, shader: &str) {
+        let after = usage();
+        if self.rss_reset {
+            println!("{}: memory", shader);
+        } else {
+            println!("{}: memory, peak RSS over the whole process", shader);
+        }
+        if let Some(rss) = peak_rss() {
+            println!("    peak RSS = {} KiB", rss / 1024);
+        }
+        if callbacks().is_none() {
+            return;
+        }
+        for (name, (before, after)) in SCOPES
+            .iter()
+            .zip(self.before.scopes.iter().zip(&after.scopes))
+        {
+            if after.peak == 0 && before.current == 0 {
+                continue;
+            }
+            println!(
+                "    host peak {} = {}",
+                name,
+                after.peak.saturating_sub(before.current)
+            );
+            println!(
+                "    host outstanding {} = {}",
+                name,
+                after.current as isize - before.current as isize
+            );
+        }
+    }
+}
+
+impl Default for Mark {
+    fn default() -> Self {
+        Self::new()
+    }
+}
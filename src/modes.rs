@@ -0,0 +1,526 @@
+use std::process::ExitCode;
+
+use ash::vk;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    baseline, bench, cache, context, descriptor, differential, driconf, env,
+    error::{self, Error, Result},
+    executable, execute, faults, fuzz,
+    identity::{self, Identity},
+    layout::SetLayout,
+    matrix, memory,
+    options::{Mode, Options},
+    owned::Owned,
+    readback, resource, runner, sweep, Context, LayoutBuilder, PipelineRunner, ShaderLoader,
+    SHADERS,
+};
+
+/// Runs what `options` ask for.
+pub fn run(options: &Options) -> Result<ExitCode> {
+    // Everything random comes from this one generator, so a run can be
+    // repeated with the seed it prints.
+    let seed = options.seed.unwrap_or_else(rand::random);
+    println!("seed {}", seed);
+    let mut passthrough = options.passthrough.clone();
+    passthrough.push(format!("--seed={}", seed));
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let profile_env = env::profiles(&options.cases, &options.env_profiles)?;
+    let resource_specs = resource::Spec::from_cases(&options.cases)?;
+    let identity = options.identity();
+    let children = Children {
+        args: &passthrough,
+        env: &profile_env,
+    };
+
+    match options.mode {
+        Mode::Driconf => return driconf(options, &identity),
+        Mode::Sweep => return sweep(options, &identity, &children),
+        Mode::Faults => return faults(options, &children),
+        Mode::Fuzz => return fuzz(options, &children, &mut rng),
+        Mode::Matrix => return matrix(options, &identity, &children),
+        _ => {}
+    }
+    match options.allocation_faults().as_slice() {
+        [] => {}
+        [fault] => memory::inject(*fault),
+        _ => {
+            return Err(Error::Usage(
+                "a range for --fail-alloc only works with faults".into(),
+            ))
+        }
+    }
+
+    if let Mode::Bench = options.mode {
+        // Mesa reads this when the device is created; every iteration has to
+        // go through the compiler. The in-memory cache is kept out of the way
+        // by `PipelineRunner::unique`.
+        std::env::set_var("MESA_SHADER_CACHE_DISABLE", "true");
+    }
+
+    for (key, value) in &profile_env {
+        std::env::set_var(key, value);
+    }
+
+    // A warm Mesa shader cache can hide compiler crashes, so every run starts
+    // with an empty one unless asked to share.
+    let temp_shader_cache = match options.shader_cache {
+        Some(_) => None,
+        None => Some(runner::TempDir::new("shader-cache")?),
+    };
+    let shader_cache_dir = options
+        .shader_cache
+        .as_deref()
+        .unwrap_or_else(|| temp_shader_cache.as_ref().unwrap().path());
+    std::env::set_var("MESA_SHADER_CACHE_DIR", shader_cache_dir);
+    env::report();
+    // Whether this run's compiles can come from the cache, decided before
+    // the device is created and can add to it.
+    match (options.mode, runner::disk_usage(shader_cache_dir)) {
+        (Mode::Bench, _) => eprintln!("shader cache: disabled"),
+        (_, 0) => eprintln!("shader cache: cold"),
+        (_, bytes) => eprintln!(
+            "shader cache: warm, {} bytes in {}",
+            bytes,
+            shader_cache_dir.display()
+        ),
+    }
+
+    if identity != Identity::default() {
+        eprintln!(
+            "application {:?} {}, engine {:?} {}, API {}",
+            identity.application_name,
+            identity::format_version(identity.application_version),
+            identity.engine_name,
+            identity::format_version(identity.engine_version),
+            identity::format_version(identity.api_version)
+        );
+    }
+
+    if options.track_memory {
+        memory::enable();
+    }
+
+    if let Mode::Differential = options.mode {
+        return differential(options, &identity, resource_specs, &mut rng);
+    }
+
+    let instance = context::Instance::new(&identity, !options.no_validation)?;
+
+    if let Mode::Inspect = options.mode {
+        cache::inspect(
+            &instance.instance,
+            &instance.physical_devices,
+            options
+                .pipeline_cache_path
+                .as_ref()
+                .ok_or_else(|| Error::Usage("inspect needs --pipeline-cache=<file>".into()))?,
+        )?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let context = Context::new(instance, options.stats || options.dump_ir.is_some())?;
+    compile(options, &context, &resource_specs, &mut rng)
+}
+
+/// How to run children for the modes that compile in child processes.
+pub struct Children<'a> {
+    /// The options that make children compile the way this run would.
+    pub args: &'a [String],
+    /// Added to each child's environment.
+    pub env: &'a [(String, String)],
+}
+
+/// Checks a driconf file, or merges an application block with the
+/// `--option=` values into one.
+pub fn driconf(options: &Options, identity: &Identity) -> Result<ExitCode> {
+    if options.check {
+        let path = options
+            .driconf_path
+            .as_ref()
+            .ok_or_else(|| Error::Usage("--check needs --driconf=<file>".into()))?;
+        if !driconf::check(path)? {
+            return Ok(ExitCode::from(1));
+        }
+    } else {
+        let dri_options = options
+            .sweep_options
+            .iter()
+            .map(|(name, values)| match values.as_slice() {
+                [value] => Ok(driconf::DriOption {
+                    comments: vec![],
+                    name: name.clone(),
+                    value: value.clone(),
+                }),
+                _ => Err(Error::Usage(format!("{} needs exactly one value", name))),
+            })
+            .collect::<Result<_>>()?;
+        driconf::emit(
+            options.driconf_path.as_deref(),
+            &options.driver,
+            driconf::Application::for_tool(&identity.application_name, dri_options),
+        )?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+pub fn sweep(options: &Options, identity: &Identity, children: &Children) -> Result<ExitCode> {
+    if options.sweep_options.is_empty() {
+        return Err(Error::Usage("sweep needs --option=<name>=<values>".into()));
+    }
+    sweep::run(
+        &options.driver,
+        &identity.application_name,
+        &options.sweep_options,
+        &options.shader_indices(),
+        children.args,
+        children.env,
+        options.shader_cache.as_deref(),
+    )?;
+    Ok(ExitCode::SUCCESS)
+}
+
+pub fn faults(options: &Options, children: &Children) -> Result<ExitCode> {
+    let allocation_faults = options.allocation_faults();
+    if allocation_faults.is_empty() {
+        return Err(Error::Usage(
+            "faults needs --fail-alloc=<n>[-<m>] or --fail-alloc-above=<bytes>".into(),
+        ));
+    }
+    faults::run(
+        &options.shader_indices(),
+        &allocation_faults,
+        children.args,
+        children.env,
+        options.shader_cache.as_deref(),
+    )?;
+    Ok(ExitCode::SUCCESS)
+}
+
+pub fn fuzz(options: &Options, children: &Children, rng: &mut StdRng) -> Result<ExitCode> {
+    // Mutated counts are meant to be scaled into the limits; skipping or
+    // ignoring them would hide what the mutations do.
+    if options.limit_policy != descriptor::LimitPolicy::Scale {
+        return Err(Error::Usage("fuzz needs --limits=scale".into()));
+    }
+    let found = fuzz::run(
+        options.shader_id.unwrap_or(0),
+        options.fuzz_runs,
+        children.args,
+        children.env,
+        options.shader_cache.as_deref(),
+        rng,
+    )?;
+    Ok(if found {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+pub fn matrix(options: &Options, identity: &Identity, children: &Children) -> Result<ExitCode> {
+    // The children compile on the preferred device; only its support is
+    // needed, not a device.
+    let instance = context::Instance::new(identity, false)?;
+    let physical_device = *instance.physical_devices.first().ok_or(Error::NoDevice)?;
+    let support = context::SubgroupSupport::query(&instance, physical_device)?;
+    matrix::run(
+        &options.shader_indices(),
+        &options.subgroup_sizes,
+        &support,
+        children.args,
+        children.env,
+        options.shader_cache.as_deref(),
+    )?;
+    Ok(ExitCode::SUCCESS)
+}
+
+pub fn differential(
+    options: &Options,
+    identity: &Identity,
+    specs: Vec<resource::Spec>,
+    rng: &mut StdRng,
+) -> Result<ExitCode> {
+    let differential_options = differential::Options {
+        devices: options.devices,
+        validation: !options.no_validation,
+        builder: LayoutBuilder::captured().push_descriptors(3).allocatable(),
+        limit_policy: options.limit_policy,
+        variable_count: options.variable_count,
+        specs,
+        groups: options.groups,
+        stage_flags: options.stage_flags,
+        subgroup_size: options.subgroup_size,
+        pipeline_flags: options.pipeline_flags,
+        tolerance: options.outputs.tolerance,
+    };
+    Ok(
+        match differential::run(identity, options.selected(), &differential_options, rng)? {
+            differential::Verdict::Same => ExitCode::SUCCESS,
+            differential::Verdict::Differs => ExitCode::from(1),
+            differential::Verdict::Skipped => ExitCode::from(error::SKIPPED),
+        },
+    )
+}
+
+/// What the modes that compile in this process share.
+pub struct Session<'a> {
+    pub context: &'a Context,
+    pub layout: vk::PipelineLayout,
+    /// The allocated descriptor sets, with `--descriptors` or to execute.
+    pub sets: Vec<vk::DescriptorSet>,
+    /// How each set of `layout` is laid out.
+    pub set_layouts: Vec<SetLayout>,
+    pub runner: PipelineRunner<'a>,
+    pub shaders: ShaderLoader,
+}
+
+/// Sets up the layout, descriptors and pipeline cache on `context` and runs
+/// the compiling modes.
+fn compile(
+    options: &Options,
+    context: &Context,
+    resource_specs: &[resource::Spec],
+    rng: &mut StdRng,
+) -> Result<ExitCode> {
+    let device = &context.device;
+
+    let flags = if context.executable_loader.is_some() {
+        vk::PipelineCreateFlags::CAPTURE_STATISTICS_KHR
+            | if options.dump_ir.is_some() {
+                vk::PipelineCreateFlags::CAPTURE_INTERNAL_REPRESENTATIONS_KHR
+            } else {
+                vk::PipelineCreateFlags::empty()
+            }
+    } else {
+        vk::PipelineCreateFlags::empty()
+    };
+
+    let mut layout_builder = LayoutBuilder::captured();
+    if let Mode::Execute = options.mode {
+        layout_builder = layout_builder.push_descriptors(3);
+    }
+    if options.descriptors || options.mode == Mode::Execute {
+        layout_builder = layout_builder.allocatable();
+    }
+    if options.fuzz_layout {
+        for mutation in fuzz::mutate(&mut layout_builder, rng) {
+            println!("mutation {}", mutation);
+        }
+    }
+    match descriptor::preflight(context, &mut layout_builder, options.limit_policy) {
+        descriptor::Preflight::Fits => {}
+        descriptor::Preflight::Scaled(changes) => {
+            for change in changes {
+                eprintln!("warning: {}", change);
+            }
+        }
+        descriptor::Preflight::Skip(reason) => {
+            eprintln!("skipped: {}", reason);
+            return Ok(ExitCode::from(error::SKIPPED));
+        }
+    }
+    let layout = layout_builder.build(device)?;
+
+    // Executing needs every set the shaders use bound.
+    let allocated = if options.descriptors || options.mode == Mode::Execute {
+        let pools = descriptor::Pool::for_layout(&layout_builder, options.variable_count);
+        for problem in descriptor::Limits::query(context).check(&layout_builder, &pools) {
+            eprintln!("descriptor limits: {}", problem);
+        }
+        let allocated = descriptor::allocate(device, &layout, &pools)?;
+        for (set, pool) in pools.iter().enumerate() {
+            match pool.variable_count {
+                _ if pool.sizes.is_empty() => println!("set {}: not allocated", set),
+                Some(count) => println!(
+                    "set {}: {} descriptors, variable count {}",
+                    set,
+                    pool.descriptor_count(),
+                    count
+                ),
+                None => println!("set {}: {} descriptors", set, pool.descriptor_count()),
+            }
+        }
+        Some(allocated)
+    } else {
+        None
+    };
+
+    // Benchmarks always bypass the cache.
+    let pipeline_cache = match (options.mode, &options.pipeline_cache_path) {
+        (Mode::Bench, _) | (_, None) => Owned::new(
+            device,
+            vk::PipelineCache::null(),
+            ash::Device::destroy_pipeline_cache,
+        ),
+        (_, Some(path)) => cache::create(device, &context.properties(), path)?,
+    };
+
+    let session = Session {
+        context,
+        layout: *layout.pipeline_layout,
+        sets: allocated
+            .as_ref()
+            .map(|allocated| allocated.sets.clone())
+            .unwrap_or_default(),
+        set_layouts: layout_builder.sets.clone(),
+        runner: PipelineRunner::new(device, *layout.pipeline_layout)
+            .cache(*pipeline_cache)
+            .flags(flags | options.pipeline_flags)
+            .stage_flags(options.stage_flags)
+            .subgroup_size(options.subgroup_size),
+        shaders: ShaderLoader::default(),
+    };
+
+    let code = match options.mode {
+        Mode::Execute => execute(options, &session, resource_specs, rng)?,
+        Mode::Single => single(options, &session, rng)?,
+        Mode::Bench => bench(options, &session)?,
+        Mode::Batch | Mode::Compare => batch(options, &session)?,
+        _ => unreachable!(),
+    };
+
+    if let Some(path) = &options.pipeline_cache_path {
+        if *pipeline_cache != vk::PipelineCache::null() {
+            cache::save(device, *pipeline_cache, path)?;
+        }
+    }
+    Ok(code)
+}
+
+pub fn execute(
+    options: &Options,
+    session: &Session,
+    resource_specs: &[resource::Spec],
+    rng: &mut StdRng,
+) -> Result<ExitCode> {
+    let executor = execute::Executor::new(session.context)?;
+    // Every shader starts from the same contents, whichever are run.
+    let specs = resource::Spec::fixed(resource_specs, rng)?;
+    let bindings = execute::Bindings {
+        layout: session.layout,
+        sets: &session.sets,
+        set_layouts: &session.set_layouts,
+        specs: &specs,
+        groups: options.groups,
+    };
+    let mut regressed = false;
+    for path in options.selected() {
+        let code = session.shaders.load(path)?;
+        let read = executor.run(&session.runner, &bindings, path, &code, rng)?;
+        regressed |= readback::check(path, &read, &options.outputs)?;
+    }
+    Ok(if regressed {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+pub fn single(options: &Options, session: &Session, rng: &mut StdRng) -> Result<ExitCode> {
+    let path = SHADERS[options
+        .shader_id
+        .unwrap_or_else(|| rng.gen_range(0..SHADERS.len()))];
+    let code = session.shaders.load(path)?;
+    let mark = options.track_memory.then(memory::Mark::new);
+    let checked = {
+        let compilation = session.runner.compile(&code)?;
+        if let (Some(loader), Ok(pipelines)) =
+            (&session.context.executable_loader, &compilation.result)
+        {
+            executable::report(loader, pipelines[0], path, options.dump_ir.as_deref())?;
+        }
+        println!(
+            "{}: {} in {:?}",
+            path,
+            compilation.outcome(),
+            compilation.compile_time
+        );
+        compilation.check(path)
+    };
+    if let Some(mark) = mark {
+        mark.report(path);
+    }
+    checked?;
+    Ok(ExitCode::SUCCESS)
+}
+
+pub fn bench(options: &Options, session: &Session) -> Result<ExitCode> {
+    let runner = PipelineRunner::new(&session.context.device, session.layout)
+        .unique()
+        .flags(options.pipeline_flags)
+        .stage_flags(options.stage_flags)
+        .subgroup_size(options.subgroup_size);
+    for path in options.selected() {
+        let code = session.shaders.load(path)?;
+        let mut samples = vec![];
+        // Over all iterations, so outstanding bytes show memory that builds
+        // up with repeated compiles.
+        let mark = options.track_memory.then(memory::Mark::new);
+        for _ in 0..options.warmup + options.iterations {
+            let compilation = runner.compile(&code)?;
+            samples.push(compilation.compile_time);
+            compilation.check(path)?;
+        }
+        bench::report(
+            path,
+            &bench::Stats::new(&samples[options.warmup..]).unwrap(),
+        );
+        if let Some(mark) = mark {
+            mark.report(path);
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Compiles every selected shader once and saves the records as a baseline
+/// or, in compare mode, compares them to one.
+pub fn batch(options: &Options, session: &Session) -> Result<ExitCode> {
+    let identity = session.context.device_identity();
+    let records = options
+        .selected()
+        .iter()
+        .map(|path| {
+            let code = session.shaders.load(path)?;
+            let mark = options.track_memory.then(memory::Mark::new);
+            let compilation = session.runner.compile(&code)?;
+            if let (Some(loader), Ok(pipelines)) =
+                (&session.context.executable_loader, &compilation.result)
+            {
+                executable::report(loader, pipelines[0], path, options.dump_ir.as_deref())?;
+            }
+            let record = baseline::Record {
+                hash: baseline::hash_code(&code),
+                device: identity.clone(),
+                shader: path.to_string(),
+                outcome: compilation.outcome(),
+                compile_time: compilation.compile_time,
+                environment: env::recorded(),
+            };
+            println!("{}: {} in {:?}", path, record.outcome, record.compile_time);
+            drop(compilation);
+            if let Some(mark) = mark {
+                mark.report(path);
+            }
+            Ok(record)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if let Mode::Compare = options.mode {
+        let path = options
+            .baseline_path
+            .as_ref()
+            .ok_or_else(|| Error::Usage("compare needs --baseline=<file>".into()))?;
+        let baseline = baseline::load(path).map_err(Error::io(path))?;
+        let comparison =
+            baseline::compare(&baseline, &records, options.selected(), options.threshold);
+        baseline::report(&comparison);
+        if !comparison.is_clean() {
+            return Ok(ExitCode::from(1));
+        }
+    } else if let Some(path) = &options.baseline_path {
+        baseline::save(path, &records).map_err(Error::io(path))?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
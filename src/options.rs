@@ -0,0 +1,633 @@
+use std::path::{Path, PathBuf};
+
+use ash::vk;
+use nom::Parser;
+
+use crate::{
+    case, descriptor,
+    error::{self, Result},
+    identity::{self, Identity},
+    matrix, memory, readback, SHADERS,
+};
+
+/// What a run does, chosen by a bare word on the command line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Single,
+    Batch,
+    Compare,
+    Bench,
+    Inspect,
+    Sweep,
+    Driconf,
+    Faults,
+    Execute,
+    Differential,
+    Fuzz,
+    Matrix,
+}
+
+/// The command line, with the options of `--case=` files in place.
+pub struct Options {
+    pub no_validation: bool,
+    /// An index into `SHADERS`.
+    pub shader_id: Option<usize>,
+    pub mode: Mode,
+    pub baseline_path: Option<PathBuf>,
+    pub pipeline_cache_path: Option<PathBuf>,
+    pub stats: bool,
+    pub track_memory: bool,
+    pub descriptors: bool,
+    pub variable_count: Option<u32>,
+    pub groups: [u32; 3],
+    pub seed: Option<u64>,
+    pub fuzz_layout: bool,
+    /// How many mutated layouts `fuzz` tries, `--fuzz-runs=`.
+    pub fuzz_runs: usize,
+    pub stage_flags: vk::PipelineShaderStageCreateFlags,
+    pub subgroup_size: Option<u32>,
+    pub pipeline_flags: vk::PipelineCreateFlags,
+    pub subgroup_sizes: Vec<u32>,
+    pub devices: [usize; 2],
+    pub outputs: readback::Options,
+    pub limit_policy: descriptor::LimitPolicy,
+    pub fail_allocs: Vec<u64>,
+    pub fail_alloc_above: Option<usize>,
+    pub dump_ir: Option<PathBuf>,
+    pub threshold: f64,
+    pub iterations: usize,
+    pub warmup: usize,
+    pub driver: String,
+    pub sweep_options: Vec<(String, Vec<String>)>,
+    pub driconf_path: Option<PathBuf>,
+    pub check: bool,
+    pub preset: Option<String>,
+    pub application_name: Option<String>,
+    pub engine_name: Option<String>,
+    pub application_version: Option<u32>,
+    pub engine_version: Option<u32>,
+    pub api_version: Option<u32>,
+    pub env_profiles: Vec<String>,
+    pub shader_cache: Option<PathBuf>,
+    /// Options child processes need to compile the same way.
+    pub passthrough: Vec<String>,
+    pub cases: Vec<case::Case>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            no_validation: false,
+            shader_id: None,
+            mode: Mode::Single,
+            baseline_path: None,
+            pipeline_cache_path: None,
+            stats: false,
+            track_memory: false,
+            descriptors: false,
+            variable_count: None,
+            groups: [1, 1, 1],
+            seed: None,
+            fuzz_layout: false,
+            fuzz_runs: 10,
+            stage_flags: vk::PipelineShaderStageCreateFlags::REQUIRE_FULL_SUBGROUPS,
+            subgroup_size: None,
+            pipeline_flags: vk::PipelineCreateFlags::empty(),
+            subgroup_sizes: matrix::SUBGROUP_SIZES.to_vec(),
+            devices: [0, 1],
+            outputs: readback::Options {
+                dump_dir: None,
+                golden_dir: None,
+                tolerance: readback::Tolerance::Exact,
+            },
+            limit_policy: descriptor::LimitPolicy::Scale,
+            fail_allocs: vec![],
+            fail_alloc_above: None,
+            dump_ir: None,
+            threshold: 10.,
+            iterations: 10,
+            warmup: 2,
+            driver: "anv".to_string(),
+            sweep_options: vec![],
+            driconf_path: None,
+            check: false,
+            preset: None,
+            application_name: None,
+            engine_name: None,
+            application_version: None,
+            engine_version: None,
+            api_version: None,
+            env_profiles: vec![],
+            shader_cache: None,
+            passthrough: vec![],
+            cases: vec![],
+        }
+    }
+}
+
+impl Options {
+    /// Parses `args`, the command line without the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut options = Options::default();
+
+        // Options from a case file go where `--case=` is, so later ones
+        // override.
+        let mut expanded = vec![];
+        for input in args {
+            match input.strip_prefix("--case=") {
+                Some(path) => {
+                    let case = case::Case::load(Path::new(path))?;
+                    expanded.extend(case.args());
+                    options.cases.push(case);
+                    options.passthrough.push(input);
+                }
+                None => expanded.push(input),
+            }
+        }
+
+        for input in expanded {
+            use nom::{
+                bytes::complete::is_not,
+                bytes::complete::tag,
+                character::complete::{char, digit1, u32, u64},
+                combinator::{all_consuming, map_res, opt, rest, verify},
+                error::Error,
+                multi::separated_list1,
+                number::complete::double,
+                sequence::{preceded, separated_pair, tuple},
+            };
+            let mut understood = false;
+            if tag::<_, _, Error<_>>("-v").parse(input.as_str()).is_ok() {
+                options.no_validation = true;
+                options.passthrough.push(input.clone());
+                understood = true;
+            }
+            if let Ok((_, id)) = preceded(
+                tag("-"),
+                verify(
+                    map_res(digit1::<_, Error<_>>, |s: &str| s.parse::<usize>()),
+                    |x| x.ge(&1) && x.le(&3),
+                ),
+            )
+            .parse(input.as_str())
+            {
+                options.shader_id = Some(id - 1);
+                understood = true;
+            }
+            if all_consuming(tag::<_, _, Error<_>>("batch"))
+                .parse(input.as_str())
+                .is_ok()
+            {
+                options.mode = Mode::Batch;
+                understood = true;
+            }
+            if all_consuming(tag::<_, _, Error<_>>("compare"))
+                .parse(input.as_str())
+                .is_ok()
+            {
+                options.mode = Mode::Compare;
+                understood = true;
+            }
+            if all_consuming(tag::<_, _, Error<_>>("bench"))
+                .parse(input.as_str())
+                .is_ok()
+            {
+                options.mode = Mode::Bench;
+                understood = true;
+            }
+            if all_consuming(tag::<_, _, Error<_>>("inspect"))
+                .parse(input.as_str())
+                .is_ok()
+            {
+                options.mode = Mode::Inspect;
+                understood = true;
+            }
+            if all_consuming(tag::<_, _, Error<_>>("execute"))
+                .parse(input.as_str())
+                .is_ok()
+            {
+                options.mode = Mode::Execute;
+                understood = true;
+            }
+            if all_consuming(tag::<_, _, Error<_>>("differential"))
+                .parse(input.as_str())
+                .is_ok()
+            {
+                options.mode = Mode::Differential;
+                understood = true;
+            }
+            if let Ok((_, (first, second))) = all_consuming(preceded(
+                tag::<_, _, Error<_>>("--devices="),
+                separated_pair(u32, char(','), u32),
+            ))
+            .parse(input.as_str())
+            {
+                options.devices = [first as usize, second as usize];
+                understood = true;
+            }
+            if let Ok((_, (x, _, y, _, z))) = all_consuming(preceded(
+                tag::<_, _, Error<_>>("--dispatch="),
+                tuple((u32, char(','), u32, char(','), u32)),
+            ))
+            .parse(input.as_str())
+            {
+                options.groups = [x, y, z];
+                understood = true;
+            }
+            if let Ok((_, path)) =
+                preceded(tag::<_, _, Error<_>>("--dump-outputs="), rest).parse(input.as_str())
+            {
+                options.outputs.dump_dir = Some(PathBuf::from(path));
+                understood = true;
+            }
+            if let Ok((_, path)) =
+                preceded(tag::<_, _, Error<_>>("--golden="), rest).parse(input.as_str())
+            {
+                options.outputs.golden_dir = Some(PathBuf::from(path));
+                understood = true;
+            }
+            if let Ok((_, ulps)) =
+                all_consuming(preceded(tag::<_, _, Error<_>>("--ulp="), u32)).parse(input.as_str())
+            {
+                options.outputs.tolerance = readback::Tolerance::Ulp(ulps);
+                understood = true;
+            }
+            if all_consuming(tag::<_, _, Error<_>>("faults"))
+                .parse(input.as_str())
+                .is_ok()
+            {
+                options.mode = Mode::Faults;
+                understood = true;
+            }
+            if let Ok((_, (first, last))) = all_consuming(preceded(
+                tag::<_, _, Error<_>>("--fail-alloc="),
+                verify(
+                    tuple((u64, opt(preceded(char('-'), u64)))),
+                    |(first, last): &(u64, Option<u64>)| {
+                        *first >= 1 && last.unwrap_or(*first) >= *first
+                    },
+                ),
+            ))
+            .parse(input.as_str())
+            {
+                options.fail_allocs = (first..=last.unwrap_or(first)).collect();
+                understood = true;
+            }
+            if let Ok((_, size)) = all_consuming(preceded(
+                tag::<_, _, Error<_>>("--fail-alloc-above="),
+                map_res(digit1, |s: &str| s.parse::<usize>()),
+            ))
+            .parse(input.as_str())
+            {
+                options.fail_alloc_above = Some(size);
+                understood = true;
+            }
+            if all_consuming(tag::<_, _, Error<_>>("sweep"))
+                .parse(input.as_str())
+                .is_ok()
+            {
+                options.mode = Mode::Sweep;
+                understood = true;
+            }
+            if let Ok((_, (name, values))) = all_consuming(preceded(
+                tag::<_, _, Error<_>>("--option="),
+                separated_pair(
+                    is_not("="),
+                    char('='),
+                    separated_list1(char(','), is_not(",")),
+                ),
+            ))
+            .parse(input.as_str())
+            {
+                options.sweep_options.push((
+                    name.to_string(),
+                    values.into_iter().map(str::to_string).collect::<Vec<_>>(),
+                ));
+                understood = true;
+            }
+            if let Ok((_, name)) = all_consuming(preceded(tag::<_, _, Error<_>>("--driver="), rest))
+                .parse(input.as_str())
+            {
+                options.driver = name.to_string();
+                understood = true;
+            }
+            if let Ok((_, count)) = all_consuming(preceded(
+                tag::<_, _, Error<_>>("--iterations="),
+                verify(map_res(digit1, |s: &str| s.parse::<usize>()), |x| x.ge(&1)),
+            ))
+            .parse(input.as_str())
+            {
+                options.iterations = count;
+                understood = true;
+            }
+            if let Ok((_, count)) = all_consuming(preceded(
+                tag::<_, _, Error<_>>("--warmup="),
+                map_res(digit1, |s: &str| s.parse::<usize>()),
+            ))
+            .parse(input.as_str())
+            {
+                options.warmup = count;
+                understood = true;
+            }
+            if let Ok((_, path)) =
+                preceded(tag::<_, _, Error<_>>("--baseline="), rest).parse(input.as_str())
+            {
+                options.baseline_path = Some(PathBuf::from(path));
+                understood = true;
+            }
+            if all_consuming(tag::<_, _, Error<_>>("--stats"))
+                .parse(input.as_str())
+                .is_ok()
+            {
+                options.stats = true;
+                understood = true;
+            }
+            if all_consuming(tag::<_, _, Error<_>>("--descriptors"))
+                .parse(input.as_str())
+                .is_ok()
+            {
+                options.descriptors = true;
+                options.passthrough.push(input.clone());
+                understood = true;
+            }
+            if let Ok((_, count)) = all_consuming(preceded(
+                tag::<_, _, Error<_>>("--variable-count="),
+                map_res(digit1, |s: &str| s.parse::<u32>()),
+            ))
+            .parse(input.as_str())
+            {
+                options.variable_count = Some(count);
+                options.passthrough.push(input.clone());
+                understood = true;
+            }
+            if let Ok((_, policy)) = all_consuming(preceded(
+                tag::<_, _, Error<_>>("--limits="),
+                map_res(rest, |s| descriptor::LimitPolicy::parse(s).ok_or(())),
+            ))
+            .parse(input.as_str())
+            {
+                options.limit_policy = policy;
+                options.passthrough.push(input.clone());
+                understood = true;
+            }
+            if all_consuming(tag::<_, _, Error<_>>("--memory"))
+                .parse(input.as_str())
+                .is_ok()
+            {
+                options.track_memory = true;
+                options.passthrough.push(input.clone());
+                understood = true;
+            }
+            if let Ok((_, path)) =
+                preceded(tag::<_, _, Error<_>>("--dump-ir="), rest).parse(input.as_str())
+            {
+                options.dump_ir = Some(PathBuf::from(path));
+                understood = true;
+            }
+            if let Ok((_, path)) =
+                preceded(tag::<_, _, Error<_>>("--pipeline-cache="), rest).parse(input.as_str())
+            {
+                options.pipeline_cache_path = Some(PathBuf::from(path));
+                understood = true;
+            }
+            if let Ok((_, percent)) = all_consuming(preceded(
+                tag::<_, _, Error<_>>("--threshold="),
+                verify(double, |x: &f64| x.ge(&0.)),
+            ))
+            .parse(input.as_str())
+            {
+                options.threshold = percent;
+                understood = true;
+            }
+            if all_consuming(tag::<_, _, Error<_>>("driconf"))
+                .parse(input.as_str())
+                .is_ok()
+            {
+                options.mode = Mode::Driconf;
+                understood = true;
+            }
+            if let Ok((_, path)) =
+                preceded(tag::<_, _, Error<_>>("--driconf="), rest).parse(input.as_str())
+            {
+                options.driconf_path = Some(PathBuf::from(path));
+                understood = true;
+            }
+            if all_consuming(tag::<_, _, Error<_>>("--check"))
+                .parse(input.as_str())
+                .is_ok()
+            {
+                options.check = true;
+                understood = true;
+            }
+            if let Ok((_, name)) = all_consuming(preceded(
+                tag::<_, _, Error<_>>("--preset="),
+                verify(rest, |name: &str| {
+                    identity::Identity::preset(name).is_some()
+                }),
+            ))
+            .parse(input.as_str())
+            {
+                options.preset = Some(name.to_string());
+                options.passthrough.push(input.clone());
+                understood = true;
+            }
+            if let Ok((_, name)) =
+                preceded(tag::<_, _, Error<_>>("--app-name="), rest).parse(input.as_str())
+            {
+                options.application_name = Some(name.to_string());
+                options.passthrough.push(input.clone());
+                understood = true;
+            }
+            if let Ok((_, name)) =
+                preceded(tag::<_, _, Error<_>>("--engine-name="), rest).parse(input.as_str())
+            {
+                options.engine_name = Some(name.to_string());
+                options.passthrough.push(input.clone());
+                understood = true;
+            }
+            if let Ok((_, Some(version))) = preceded(
+                tag::<_, _, Error<_>>("--app-version="),
+                rest.map(identity::parse_version),
+            )
+            .parse(input.as_str())
+            {
+                options.application_version = Some(version);
+                options.passthrough.push(input.clone());
+                understood = true;
+            }
+            if let Ok((_, Some(version))) = preceded(
+                tag::<_, _, Error<_>>("--engine-version="),
+                rest.map(identity::parse_version),
+            )
+            .parse(input.as_str())
+            {
+                options.engine_version = Some(version);
+                options.passthrough.push(input.clone());
+                understood = true;
+            }
+            if let Ok((_, Some(version))) = preceded(
+                tag::<_, _, Error<_>>("--api-version="),
+                rest.map(identity::parse_version),
+            )
+            .parse(input.as_str())
+            {
+                options.api_version = Some(version);
+                options.passthrough.push(input.clone());
+                understood = true;
+            }
+            if let Ok((_, name)) =
+                all_consuming(preceded(tag::<_, _, Error<_>>("--env="), rest)).parse(input.as_str())
+            {
+                options.env_profiles.push(name.to_string());
+                understood = true;
+            }
+            if let Ok((_, path)) =
+                preceded(tag::<_, _, Error<_>>("--shader-cache="), rest).parse(input.as_str())
+            {
+                options.shader_cache = Some(PathBuf::from(path));
+                understood = true;
+            }
+            if all_consuming(tag::<_, _, Error<_>>("fuzz"))
+                .parse(input.as_str())
+                .is_ok()
+            {
+                options.mode = Mode::Fuzz;
+                understood = true;
+            }
+            if all_consuming(tag::<_, _, Error<_>>("--fuzz-layout"))
+                .parse(input.as_str())
+                .is_ok()
+            {
+                options.fuzz_layout = true;
+                understood = true;
+            }
+            if let Ok((_, count)) = all_consuming(preceded(
+                tag::<_, _, Error<_>>("--fuzz-runs="),
+                verify(map_res(digit1, |s: &str| s.parse::<usize>()), |x| x.ge(&1)),
+            ))
+            .parse(input.as_str())
+            {
+                options.fuzz_runs = count;
+                understood = true;
+            }
+            if let Ok((_, value)) =
+                all_consuming(preceded(tag::<_, _, Error<_>>("--seed="), u64)).parse(input.as_str())
+            {
+                options.seed = Some(value);
+                understood = true;
+            }
+            if all_consuming(tag::<_, _, Error<_>>("matrix"))
+                .parse(input.as_str())
+                .is_ok()
+            {
+                options.mode = Mode::Matrix;
+                understood = true;
+            }
+            if let Ok((_, flags)) = all_consuming(preceded(
+                tag::<_, _, Error<_>>("--stage-flags="),
+                map_res(rest, |s| matrix::parse(&matrix::STAGE_FLAGS, s).ok_or(())),
+            ))
+            .parse(input.as_str())
+            {
+                options.stage_flags = flags;
+                options.passthrough.push(input.clone());
+                understood = true;
+            }
+            if let Ok((_, size)) = all_consuming(preceded(
+                tag::<_, _, Error<_>>("--subgroup-size="),
+                verify(u32, |x: &u32| x.is_power_of_two()),
+            ))
+            .parse(input.as_str())
+            {
+                options.subgroup_size = Some(size);
+                options.passthrough.push(input.clone());
+                understood = true;
+            }
+            if let Ok((_, flags)) = all_consuming(preceded(
+                tag::<_, _, Error<_>>("--pipeline-flags="),
+                map_res(rest, |s| {
+                    matrix::parse(&matrix::PIPELINE_FLAGS, s).ok_or(())
+                }),
+            ))
+            .parse(input.as_str())
+            {
+                options.pipeline_flags = flags;
+                options.passthrough.push(input.clone());
+                understood = true;
+            }
+            if let Ok((_, sizes)) = all_consuming(preceded(
+                tag::<_, _, Error<_>>("--subgroup-sizes="),
+                separated_list1(char(','), verify(u32, |x: &u32| x.is_power_of_two())),
+            ))
+            .parse(input.as_str())
+            {
+                options.subgroup_sizes = sizes;
+                understood = true;
+            }
+            if input.as_str() == "" {
+                understood = true;
+            }
+            if !understood {
+                return Err(error::Error::Usage(format!("not understood: {}", input)));
+            }
+        }
+        Ok(options)
+    }
+
+    /// `VkApplicationInfo` as the preset and identity options make it.
+    pub fn identity(&self) -> Identity {
+        let mut identity = self
+            .preset
+            .as_deref()
+            .and_then(Identity::preset)
+            .unwrap_or_default();
+        if let Some(name) = &self.application_name {
+            identity.application_name = name.clone();
+        }
+        if let Some(name) = &self.engine_name {
+            identity.engine_name = name.clone();
+        }
+        if let Some(version) = self.application_version {
+            identity.application_version = version;
+        }
+        if let Some(version) = self.engine_version {
+            identity.engine_version = version;
+        }
+        if let Some(version) = self.api_version {
+            identity.api_version = version;
+        }
+        identity
+    }
+
+    /// The faults `--fail-alloc=` and `--fail-alloc-above=` ask for, one per
+    /// allocation in a range.
+    pub fn allocation_faults(&self) -> Vec<memory::Fault> {
+        match (self.fail_allocs.as_slice(), self.fail_alloc_above) {
+            ([], None) => vec![],
+            ([], above) => vec![memory::Fault { nth: None, above }],
+            (nths, above) => nths
+                .iter()
+                .map(|&nth| memory::Fault {
+                    nth: Some(nth),
+                    above,
+                })
+                .collect(),
+        }
+    }
+
+    /// Indices into `SHADERS` of the shader selected with `-N`, or of all.
+    pub fn shader_indices(&self) -> Vec<usize> {
+        match self.shader_id {
+            Some(id) => vec![id],
+            None => (0..SHADERS.len()).collect(),
+        }
+    }
+
+    /// The paths of the shader selected with `-N`, or of all.
+    pub fn selected(&self) -> &'static [&'static str] {
+        match self.shader_id {
+            Some(id) => &SHADERS[id..=id],
+            None => &SHADERS[..],
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use std::ops::Deref;
+
+use ash::vk;
+
+use crate::memory;
+
+/// The `ash::Device::destroy_*` function for a handle type.
+pub type Destroy<T> = unsafe fn(&ash::Device, T, Option<&vk::AllocationCallbacks>);
+
+/// A device child that is destroyed when dropped. Borrowing the device keeps
+/// it alive until every object created from it is gone.
+pub struct Owned<'a, T: Copy> {
+    device: &'a ash::Device,
+    handle: T,
+    destroy: Destroy<T>,
+}
+
+impl<'a, T: Copy> Owned<'a, T> {
+    /// Takes ownership of `handle`. A null handle is fine; destroying one is
+    /// a no-op.
+    pub fn new(device: &'a ash::Device, handle: T, destroy: Destroy<T>) -> Self {
+        Owned {
+            device,
+            handle,
+            destroy,
+        }
+    }
+}
+
+impl<T: Copy> Deref for Owned<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.handle
+    }
+}
+
+impl<T: Copy> Drop for Owned<'_, T> {
+    fn drop(&mut self) {
+        unsafe { (self.destroy)(self.device, self.handle, memory::callbacks()) };
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    cell::Cell,
+    time::{Duration, Instant},
+};
+
+use ash::vk;
+
+use crate::{
+    baseline,
+    error::{Error, Result},
+    memory,
+    owned::Owned,
+};
+
+/// One `create_compute_pipelines` call. The pipelines are destroyed on drop.
+pub struct Compilation<'a> {
+    device: &'a ash::Device,
+    pub result: std::result::Result<Vec<vk::Pipeline>, (Vec<vk::Pipeline>, vk::Result)>,
+    /// Time spent in `create_compute_pipelines` only.
+    pub compile_time: Duration,
+}
+
+impl Compilation<'_> {
+    pub fn outcome(&self) -> baseline::Outcome {
+        match &self.result {
+            Ok(_) => baseline::Outcome::Pass,
+            Err((_, result)) => baseline::Outcome::Fail(*result),
+        }
+    }
+
+    /// The failed compile as an error naming `shader`.
+    pub fn check(&self, shader: &str) -> Result<()> {
+        match &self.result {
+            Ok(_) => Ok(()),
+            Err((_, result)) => Err(Error::Pipeline {
+                shader: shader.to_string(),
+                result: *result,
+            }),
+        }
+    }
+}
+
+impl Drop for Compilation<'_> {
+    fn drop(&mut self) {
+        // A failed call can still return pipelines for the other create
+        // infos; the rest are null.
+        let (Ok(pipelines) | Err((pipelines, _))) = &self.result;
+        for pipeline in pipelines {
+            unsafe { self.device.destroy_pipeline(*pipeline, memory::callbacks()) };
+        }
+    }
+}
+
+/// The specialization constant id [`PipelineRunner::unique`] sets. No shader
+/// declares it; specializing an id a shader doesn't declare is allowed and
+/// ignored by the compiler.
+const NONCE_ID: u32 = u32::MAX;
+
+/// Builds compute pipelines against one pipeline layout.
+pub struct PipelineRunner<'a> {
+    device: &'a ash::Device,
+    layout: vk::PipelineLayout,
+    cache: vk::PipelineCache,
+    flags: vk::PipelineCreateFlags,
+    stage_flags: vk::PipelineShaderStageCreateFlags,
+    subgroup_size: Option<u32>,
+    /// The next nonce, if every compile is to be unique.
+    nonce: Option<Cell<u32>>,
+}
+
+impl<'a> PipelineRunner<'a> {
+    pub fn new(device: &'a ash::Device, layout: vk::PipelineLayout) -> Self {
+        PipelineRunner {
+            device,
+            layout,
+            cache: vk::PipelineCache::null(),
+            flags: vk::PipelineCreateFlags::empty(),
+            stage_flags: vk::PipelineShaderStageCreateFlags::REQUIRE_FULL_SUBGROUPS,
+            subgroup_size: None,
+            nonce: None,
+        }
+    }
+
+    pub fn cache(mut self, cache: vk::PipelineCache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    pub fn flags(mut self, flags: vk::PipelineCreateFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Replaces the default `REQUIRE_FULL_SUBGROUPS`.
+    pub fn stage_flags(mut self, stage_flags: vk::PipelineShaderStageCreateFlags) -> Self {
+        self.stage_flags = stage_flags;
+        self
+    }
+
+    /// Asks for this subgroup size with
+    /// `VkPipelineShaderStageRequiredSubgroupSizeCreateInfo`.
+    pub fn subgroup_size(mut self, subgroup_size: Option<u32>) -> Self {
+        self.subgroup_size = subgroup_size;
+        self
+    }
+
+    /// Makes every compile unique with a counter in a specialization constant.
+    /// Mesa drivers hash the specialization info into the key of their
+    /// in-memory cache, which stays in use with a null pipeline cache and
+    /// `MESA_SHADER_CACHE_DISABLE`, so otherwise only the first compile of a
+    /// shader would reach the compiler.
+    pub fn unique(mut self) -> Self {
+        self.nonce = Some(Cell::new(0));
+        self
+    }
+
+    /// Builds a compute pipeline from `code`. Only failing to get as far as
+    /// compiling is an error; a failed compile is part of the result.
+    pub fn compile(&self, code: &[u32]) -> Result<Compilation<'a>> {
+        let create_info = vk::ShaderModuleCreateInfo::builder().code(code);
+
+        let shader_module = unsafe {
+            self.device
+                .create_shader_module(&create_info, memory::callbacks())
+        }
+        .map_err(Error::vulkan("vkCreateShaderModule"))?;
+        let shader_module = Owned::new(
+            self.device,
+            shader_module,
+            ash::Device::destroy_shader_module,
+        );
+
+        let mut subgroup_size = vk::PipelineShaderStageRequiredSubgroupSizeCreateInfo::builder()
+            .required_subgroup_size(self.subgroup_size.unwrap_or_default());
+        let mut stage = vk::PipelineShaderStageCreateInfo::builder()
+            .flags(self.stage_flags)
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(*shader_module)
+            .name(c"main");
+        if self.subgroup_size.is_some() {
+            stage = stage.push_next(&mut subgroup_size);
+        }
+        let nonce = self.nonce.as_ref().map(|nonce| {
+            let value = nonce.get();
+            nonce.set(value.wrapping_add(1));
+            value.to_ne_bytes()
+        });
+        let map_entries = [vk::SpecializationMapEntry {
+            constant_id: NONCE_ID,
+            offset: 0,
+            size: std::mem::size_of::<u32>(),
+        }];
+        let specialization_info;
+        if let Some(nonce) = &nonce {
+            specialization_info = vk::SpecializationInfo::builder()
+                .map_entries(&map_entries)
+                .data(nonce);
+            stage = stage.specialization_info(&specialization_info);
+        }
+        let create_info = vk::ComputePipelineCreateInfo::builder()
+            .stage(*stage)
+            .flags(self.flags)
+            .layout(self.layout);
+
+        let start = Instant::now();
+        let result = unsafe {
+            self.device
+                .create_compute_pipelines(self.cache, &[*create_info], memory::callbacks())
+        };
+        let compile_time = start.elapsed();
+
+        Ok(Compilation {
+            device: self.device,
+            result,
+            compile_time,
+        })
+    }
+}
@@ -0,0 +1,256 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    baseline,
+    error::{Error, Result},
+};
+
+/// The contents of the writable resources after a dispatch, by name.
+pub type Outputs = Vec<(String, Vec<u8>)>;
+
+/// How close an output has to be to its golden copy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tolerance {
+    /// Every bit.
+    Exact,
+    /// Words taken as `f32` may be this many units in the last place apart.
+    /// NaNs match any NaN.
+    Ulp(u32),
+}
+
+/// What to do with the outputs of a dispatch.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Write each output to `<dir>/<shader>-<resource>.bin`.
+    pub dump_dir: Option<PathBuf>,
+    /// Compare each output to the file of the same name here, as dumped from
+    /// a run that is known to be good, e.g. on lavapipe.
+    pub golden_dir: Option<PathBuf>,
+    pub tolerance: Tolerance,
+}
+
+/// Outputs compared at most this many words apart are listed.
+const LISTED: usize = 8;
+
+/// The file an output of `resource` from `shader` is dumped to.
+pub fn file_name(shader: &str, resource: &str) -> String {
+    let stem = Path::new(shader)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| shader.to_string());
+    format!("{}-{}.bin", stem, resource)
+}
+
+fn word(bytes: &[u8], index: usize) -> u32 {
+    let mut word = [0; 4];
+    for (byte, value) in word.iter_mut().zip(bytes.iter().skip(index * 4)) {
+        *byte = *value;
+    }
+    u32::from_le_bytes(word)
+}
+
+/// How many `f32` values lie between `a` and `b`.
+pub fn ulp_distance(a: f32, b: f32) -> u64 {
+    // Maps the sign-magnitude bit patterns onto a line where neighbouring
+    // floats are neighbouring integers and both zeros meet.
+    let ordered = |x: f32| {
+        let bits = x.to_bits();
+        if bits & 0x8000_0000 != 0 {
+            -((bits & 0x7fff_ffff) as i64)
+        } else {
+            bits as i64
+        }
+    };
+    ordered(a).abs_diff(ordered(b))
+}
+
+/// The byte offsets of the 32-bit words in which `actual` differs from
+/// `expected` by more than `tolerance`. A trailing partial word is compared
+/// zero padded, and words only one side has all differ.
+pub fn differences(expected: &[u8], actual: &[u8], tolerance: Tolerance) -> Vec<usize> {
+    let words = expected.len().max(actual.len()).div_ceil(4);
+    (0..words)
+        .filter(|&index| {
+            if index * 4 >= expected.len() || index * 4 >= actual.len() {
+                return true;
+            }
+            let (expected, actual) = (word(expected, index), word(actual, index));
+            match tolerance {
+                Tolerance::Exact => expected != actual,
+                Tolerance::Ulp(ulps) => {
+                    let (expected, actual) = (f32::from_bits(expected), f32::from_bits(actual));
+                    if expected.is_nan() || actual.is_nan() {
+                        expected.is_nan() != actual.is_nan()
+                    } else {
+                        ulp_distance(expected, actual) > ulps as u64
+                    }
+                }
+            }
+        })
+        .map(|index| index * 4)
+        .collect()
+}
+
+/// Prints where `actual` and `expected` differ, the first few words with
+/// both values.
+pub fn report_differences(label: &str, expected: &[u8], actual: &[u8], offsets: &[usize]) {
+    if expected.len() != actual.len() {
+        println!(
+            "{}: {} bytes, expected {}",
+            label,
+            actual.len(),
+            expected.len()
+        );
+    }
+    println!(
+        "{}: {} of {} words differ",
+        label,
+        offsets.len(),
+        expected.len().max(actual.len()).div_ceil(4)
+    );
+    for &offset in offsets.iter().take(LISTED) {
+        println!(
+            "    {:#x}: expected {:08x}, got {:08x}",
+            offset,
+            word(expected, offset / 4),
+            word(actual, offset / 4)
+        );
+    }
+}
+
+/// Prints the hash of each of `outputs`, as (resource, contents), dumps and
+/// compares them as `options` say. Returns whether any differs from its
+/// golden copy.
+pub fn check(shader: &str, outputs: &Outputs, options: &Options) -> Result<bool> {
+    let mut differs = false;
+    for (resource, bytes) in outputs {
+        println!(
+            "{}: {} hash {:016x}",
+            shader,
+            resource,
+            baseline::hash_bytes(bytes.iter().copied())
+        );
+        let name = file_name(shader, resource);
+        if let Some(dir) = &options.dump_dir {
+            std::fs::create_dir_all(dir).map_err(Error::io(dir))?;
+            let path = dir.join(&name);
+            std::fs::write(&path, bytes).map_err(Error::io(&path))?;
+        }
+        if let Some(dir) = &options.golden_dir {
+            let path = dir.join(&name);
+            let golden = std::fs::read(&path).map_err(Error::io(&path))?;
+            let offsets = differences(&golden, bytes, options.tolerance);
+            if !offsets.is_empty() {
+                report_differences(
+                    &format!("{}: {} against {}", shader, resource, path.display()),
+                    &golden,
+                    bytes,
+                    &offsets,
+                );
+                differs = true;
+            }
+        }
+    }
+    Ok(differs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(values: &[f32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn ulp_distances() {
+        assert_eq!(ulp_distance(1.0, 1.0), 0);
+        assert_eq!(ulp_distance(1.0, f32::from_bits(1.0f32.to_bits() + 1)), 1);
+        assert_eq!(ulp_distance(f32::from_bits(1.0f32.to_bits() + 3), 1.0), 3);
+        // Both zeros are the same point, and the smallest subnormals either
+        // side of them one step away.
+        assert_eq!(ulp_distance(0.0, -0.0), 0);
+        assert_eq!(ulp_distance(0.0, f32::from_bits(1)), 1);
+        assert_eq!(ulp_distance(-0.0, -f32::from_bits(1)), 1);
+        assert_eq!(ulp_distance(-f32::from_bits(1), f32::from_bits(1)), 2);
+        assert_eq!(ulp_distance(-1.0, 1.0), 2 * 1.0f32.to_bits() as u64);
+        assert_eq!(ulp_distance(f32::MAX, f32::INFINITY), 1);
+    }
+
+    #[test]
+    fn exact_differences() {
+        let expected = bytes(&[1.0, 2.0, 3.0]);
+        assert!(differences(&expected, &expected, Tolerance::Exact).is_empty());
+        assert_eq!(
+            differences(&expected, &bytes(&[1.0, 2.5, 3.0]), Tolerance::Exact),
+            [4]
+        );
+        // The zeros differ in bits.
+        assert_eq!(
+            differences(&bytes(&[0.0]), &bytes(&[-0.0]), Tolerance::Exact),
+            [0]
+        );
+    }
+
+    #[test]
+    fn lengths_and_partial_words() {
+        let expected = bytes(&[1.0, 2.0]);
+        assert_eq!(
+            differences(&expected, &expected[..4], Tolerance::Exact),
+            [4]
+        );
+        assert_eq!(
+            differences(&expected[..4], &expected, Tolerance::Ulp(0)),
+            [4]
+        );
+        assert!(differences(&[1, 2, 3], &[1, 2, 3], Tolerance::Exact).is_empty());
+        assert_eq!(differences(&[1, 2, 3], &[1, 2, 4], Tolerance::Exact), [0]);
+        assert!(differences(&[], &[], Tolerance::Exact).is_empty());
+    }
+
+    #[test]
+    fn ulp_differences() {
+        let two_up = f32::from_bits(1.0f32.to_bits() + 2);
+        assert!(differences(&bytes(&[1.0]), &bytes(&[two_up]), Tolerance::Ulp(2)).is_empty());
+        assert_eq!(
+            differences(&bytes(&[1.0]), &bytes(&[two_up]), Tolerance::Ulp(1)),
+            [0]
+        );
+        assert!(differences(&bytes(&[0.0]), &bytes(&[-0.0]), Tolerance::Ulp(0)).is_empty());
+        assert_eq!(
+            differences(&bytes(&[1.0]), &bytes(&[-1.0]), Tolerance::Ulp(1000)),
+            [0]
+        );
+    }
+
+    #[test]
+    fn nans_match_any_nan() {
+        let other_nan = f32::from_bits(0xffc0_0001);
+        assert!(
+            differences(&bytes(&[f32::NAN]), &bytes(&[other_nan]), Tolerance::Ulp(0)).is_empty()
+        );
+        assert_eq!(
+            differences(
+                &bytes(&[f32::NAN]),
+                &bytes(&[1.0]),
+                Tolerance::Ulp(u32::MAX)
+            ),
+            [0]
+        );
+        assert_eq!(
+            differences(
+                &bytes(&[1.0]),
+                &bytes(&[f32::NAN]),
+                Tolerance::Ulp(u32::MAX)
+            ),
+            [0]
+        );
+        assert_eq!(
+            differences(&bytes(&[f32::NAN]), &bytes(&[other_nan]), Tolerance::Exact),
+            [0]
+        );
+    }
+}
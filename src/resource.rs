@@ -0,0 +1,1059 @@
+use std::path::PathBuf;
+
+use ash::vk;
+use nom::Parser;
+use rand::{rngs::StdRng, RngCore};
+
+use crate::{
+    case,
+    error::{Error, Result},
+    execute::{Executor, Pushed},
+    layout::SetLayout,
+    memory,
+    owned::Owned,
+    Context,
+};
+
+/// What a resource is, and so which descriptor type it is bound as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    StorageBuffer,
+    UniformBuffer,
+    UniformTexelBuffer,
+    StorageTexelBuffer,
+    SampledImage,
+    StorageImage,
+}
+
+impl Kind {
+    pub fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "storage-buffer" => Kind::StorageBuffer,
+            "uniform-buffer" => Kind::UniformBuffer,
+            "uniform-texel-buffer" => Kind::UniformTexelBuffer,
+            "storage-texel-buffer" => Kind::StorageTexelBuffer,
+            "sampled-image" => Kind::SampledImage,
+            "storage-image" => Kind::StorageImage,
+            _ => return None,
+        })
+    }
+
+    pub fn descriptor_type(self) -> vk::DescriptorType {
+        match self {
+            Kind::StorageBuffer => vk::DescriptorType::STORAGE_BUFFER,
+            Kind::UniformBuffer => vk::DescriptorType::UNIFORM_BUFFER,
+            Kind::UniformTexelBuffer => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+            Kind::StorageTexelBuffer => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+            Kind::SampledImage => vk::DescriptorType::SAMPLED_IMAGE,
+            Kind::StorageImage => vk::DescriptorType::STORAGE_IMAGE,
+        }
+    }
+
+    pub fn is_image(self) -> bool {
+        matches!(self, Kind::SampledImage | Kind::StorageImage)
+    }
+
+    /// Whether shaders can write it, which is what gets read back.
+    pub fn is_writable(self) -> bool {
+        matches!(
+            self,
+            Kind::StorageBuffer | Kind::StorageTexelBuffer | Kind::StorageImage
+        )
+    }
+
+    fn buffer_usage(self) -> vk::BufferUsageFlags {
+        match self {
+            Kind::StorageBuffer => vk::BufferUsageFlags::STORAGE_BUFFER,
+            Kind::UniformBuffer => vk::BufferUsageFlags::UNIFORM_BUFFER,
+            Kind::UniformTexelBuffer => vk::BufferUsageFlags::UNIFORM_TEXEL_BUFFER,
+            Kind::StorageTexelBuffer => vk::BufferUsageFlags::STORAGE_TEXEL_BUFFER,
+            Kind::SampledImage | Kind::StorageImage => vk::BufferUsageFlags::empty(),
+        }
+    }
+
+    fn image_usage(self) -> vk::ImageUsageFlags {
+        let transfer = vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST;
+        match self {
+            Kind::SampledImage => vk::ImageUsageFlags::SAMPLED | transfer,
+            Kind::StorageImage => vk::ImageUsageFlags::STORAGE | transfer,
+            _ => vk::ImageUsageFlags::empty(),
+        }
+    }
+}
+
+/// The formats texel buffers and images can have, with their texel size.
+const FORMATS: [(&str, vk::Format, u64); 5] = [
+    ("r32-uint", vk::Format::R32_UINT, 4),
+    ("r32-sfloat", vk::Format::R32_SFLOAT, 4),
+    ("rgba8-unorm", vk::Format::R8G8B8A8_UNORM, 4),
+    ("rgba32-uint", vk::Format::R32G32B32A32_UINT, 16),
+    ("rgba32-sfloat", vk::Format::R32G32B32A32_SFLOAT, 16),
+];
+
+/// The initial contents of a resource.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Init {
+    Zeros,
+    Random,
+    /// Bytes from a file, zero padded or cut to the resource size.
+    File(PathBuf),
+    /// These bytes, likewise; for giving several runs the same random
+    /// contents.
+    Bytes(Vec<u8>),
+}
+
+impl Init {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "zeros" => Some(Init::Zeros),
+            "random" => Some(Init::Random),
+            _ => value
+                .strip_prefix("file:")
+                .map(|path| Init::File(PathBuf::from(path))),
+        }
+    }
+
+    /// The bytes to start with; random ones come from `rng`.
+    pub fn contents(&self, size: u64, rng: &mut StdRng) -> Result<Vec<u8>> {
+        let mut bytes = match self {
+            Init::Zeros => vec![],
+            Init::Random => {
+                let mut bytes = vec![0; size as usize];
+                rng.fill_bytes(&mut bytes);
+                bytes
+            }
+            Init::File(path) => std::fs::read(path).map_err(Error::io(path))?,
+            Init::Bytes(bytes) => bytes.clone(),
+        };
+        bytes.resize(size as usize, 0);
+        Ok(bytes)
+    }
+}
+
+/// How the contents of a resource are laid out, for reporting differences.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Element {
+    U32,
+    F32,
+    Vec4,
+}
+
+impl Element {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "u32" => Some(Element::U32),
+            "f32" => Some(Element::F32),
+            "vec4" => Some(Element::Vec4),
+            _ => None,
+        }
+    }
+
+    /// Bytes per element.
+    pub fn size(self) -> usize {
+        match self {
+            Element::U32 | Element::F32 => 4,
+            Element::Vec4 => 16,
+        }
+    }
+
+    /// Element `index` of `bytes`, missing bytes as zeros.
+    pub fn format(self, bytes: &[u8], index: usize) -> String {
+        let word = |index: usize| {
+            let mut word = [0; 4];
+            for (byte, value) in word.iter_mut().zip(bytes.iter().skip(index * 4)) {
+                *byte = *value;
+            }
+            u32::from_le_bytes(word)
+        };
+        let float = |index: usize| f32::from_bits(word(index));
+        match self {
+            Element::U32 => format!("{}", word(index)),
+            Element::F32 => format!("{:?}", float(index)),
+            Element::Vec4 => format!(
+                "({:?}, {:?}, {:?}, {:?})",
+                float(index * 4),
+                float(index * 4 + 1),
+                float(index * 4 + 2),
+                float(index * 4 + 3)
+            ),
+        }
+    }
+}
+
+/// Where a resource's descriptor goes: an array element of a binding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Slot {
+    pub set: u32,
+    pub binding: u32,
+    pub element: u32,
+}
+
+/// A resource as described by a `[resource <name>]` case file section:
+///
+/// ```text
+/// [resource output]
+/// kind = storage-image
+/// format = rgba32-sfloat
+/// extent = 64x64
+/// init = random
+/// bind = 1.2.7
+/// ```
+///
+/// Buffers take `size` in bytes instead of `extent`; texel buffers take a
+/// `format` too. `init` is `zeros` (the default), `random` or
+/// `file:<path>`. `bind` is `set.binding` or `set.binding.element`; without
+/// it the resource is created but not bound. `type` is what the contents
+/// hold, `u32` (the default), `f32` or `vec4`, for comparing them.
+///
+/// For shaders that dereference pointers, a bare `device-address` line
+/// makes a buffer usable through its address, and `address@<offset> = <name>`
+/// writes the address of buffer `<name>` into this buffer at byte
+/// `<offset>`, after `init`. That is how a uniform buffer, including the
+/// pushed one of set 3, hands the shader its pointers:
+///
+/// ```text
+/// [resource ubo]
+/// kind = uniform-buffer
+/// size = 256
+/// bind = 3.0
+/// address@16 = output
+/// ```
+#[derive(Clone, Debug)]
+pub struct Spec {
+    pub name: String,
+    pub kind: Kind,
+    pub format: vk::Format,
+    /// Bytes for buffers, width times height times texel size for images.
+    pub size: u64,
+    pub extent: [u32; 2],
+    pub init: Init,
+    pub slot: Option<Slot>,
+    pub element: Element,
+    pub device_address: bool,
+    /// Byte offsets and the buffers whose addresses go there.
+    pub addresses: Vec<(u64, String)>,
+}
+
+impl Spec {
+    pub fn from_section(section: &case::Section) -> Result<Self> {
+        use nom::{
+            bytes::complete::tag,
+            character::complete::{char, u32, u64},
+            combinator::{all_consuming, opt},
+            error::Error,
+            sequence::{preceded, separated_pair, tuple},
+        };
+        let invalid = |key: &str, value: &str| {
+            crate::error::Error::Usage(format!(
+                "[resource {}]: invalid {}: {}",
+                section.name, key, value
+            ))
+        };
+        let missing = |key: &str| {
+            crate::error::Error::Usage(format!("[resource {}]: no {}", section.name, key))
+        };
+
+        let mut kind = None;
+        let mut format = None;
+        let mut size = None;
+        let mut extent = None;
+        let mut init = Init::Zeros;
+        let mut slot = None;
+        let mut element = Element::U32;
+        let mut device_address = false;
+        let mut addresses = vec![];
+        for (key, value) in &section.entries {
+            let value = value.as_str();
+            match key.as_str() {
+                "kind" => kind = Some(Kind::parse(value).ok_or_else(|| invalid(key, value))?),
+                "format" => {
+                    format = Some(
+                        FORMATS
+                            .iter()
+                            .find(|(name, ..)| *name == value)
+                            .ok_or_else(|| invalid(key, value))?,
+                    )
+                }
+                "size" => {
+                    size = Some(
+                        all_consuming(u64::<_, Error<_>>)
+                            .parse(value)
+                            .map_err(|_| invalid(key, value))?
+                            .1,
+                    )
+                }
+                "extent" => {
+                    extent = Some(
+                        all_consuming(separated_pair(u32::<_, Error<_>>, char('x'), u32))
+                            .parse(value)
+                            .map_err(|_| invalid(key, value))?
+                            .1,
+                    )
+                }
+                "init" => init = Init::parse(value).ok_or_else(|| invalid(key, value))?,
+                "bind" => {
+                    let (_, (set, binding, element)) = all_consuming(tuple((
+                        u32::<_, Error<_>>,
+                        preceded(char('.'), u32),
+                        opt(preceded(char('.'), u32)),
+                    )))
+                    .parse(value)
+                    .map_err(|_| invalid(key, value))?;
+                    slot = Some(Slot {
+                        set,
+                        binding,
+                        element: element.unwrap_or(0),
+                    });
+                }
+                "type" => element = Element::parse(value).ok_or_else(|| invalid(key, value))?,
+                "device-address" => device_address = true,
+                _ if key.starts_with("address@") => {
+                    let (_, offset) = all_consuming(preceded(tag("address@"), u64::<_, Error<_>>))
+                        .parse(key.as_str())
+                        .map_err(|_| invalid("key", key))?;
+                    addresses.push((offset, value.to_string()));
+                }
+                _ => return Err(invalid("key", key)),
+            }
+        }
+
+        let kind = kind.ok_or_else(|| missing("kind"))?;
+        let (format, texel_size) = match (kind, format) {
+            // Only texel buffers get a view to give the format to.
+            (Kind::StorageBuffer | Kind::UniformBuffer, Some(_)) => {
+                return Err(invalid("key", "format is for texel buffers and images"))
+            }
+            (_, Some(&(_, format, texel_size))) => (format, texel_size),
+            (Kind::StorageBuffer | Kind::UniformBuffer, None) => (vk::Format::UNDEFINED, 1),
+            (_, None) => return Err(missing("format")),
+        };
+        if kind.is_image() && (device_address || !addresses.is_empty()) {
+            return Err(invalid("key", "images have no device address"));
+        }
+        let (size, extent) = if kind.is_image() {
+            let (width, height) = extent.ok_or_else(|| missing("extent"))?;
+            (width as u64 * height as u64 * texel_size, [width, height])
+        } else {
+            let size = size.ok_or_else(|| missing("size"))?;
+            (size, [0, 0])
+        };
+        Ok(Spec {
+            name: section.name.clone(),
+            kind,
+            format,
+            size,
+            extent,
+            init,
+            slot,
+            element,
+            device_address,
+            addresses,
+        })
+    }
+
+    /// `specs` with their initial contents made now, so resources created
+    /// from them again start out the same.
+    pub fn fixed(specs: &[Spec], rng: &mut StdRng) -> Result<Vec<Spec>> {
+        specs
+            .iter()
+            .map(|spec| {
+                Ok(Spec {
+                    init: Init::Bytes(spec.init.contents(spec.size, rng)?),
+                    ..spec.clone()
+                })
+            })
+            .collect()
+    }
+
+    /// Every `[resource]` section in `cases`. A name defined again in a later
+    /// case file replaces the earlier one.
+    pub fn from_cases(cases: &[case::Case]) -> Result<Vec<Self>> {
+        let mut specs: Vec<Spec> = vec![];
+        for section in cases
+            .iter()
+            .flat_map(|case| &case.sections)
+            .filter(|section| section.kind == "resource")
+        {
+            let spec = Spec::from_section(section)?;
+            specs.retain(|existing| existing.name != spec.name);
+            specs.push(spec);
+        }
+
+        for spec in &specs {
+            for (offset, name) in &spec.addresses {
+                if offset + 8 > spec.size {
+                    return Err(Error::Usage(format!(
+                        "[resource {}]: address@{} is past the end",
+                        spec.name, offset
+                    )));
+                }
+                if !specs
+                    .iter()
+                    .any(|target| target.name == *name && target.device_address)
+                {
+                    return Err(Error::Usage(format!(
+                        "[resource {}]: address@{}: no [resource {}] with device-address",
+                        spec.name, offset, name
+                    )));
+                }
+            }
+        }
+        Ok(specs)
+    }
+}
+
+/// The first memory type allowed by `type_bits` with all of `flags`.
+pub fn memory_type(
+    context: &Context,
+    type_bits: u32,
+    flags: vk::MemoryPropertyFlags,
+) -> Option<u32> {
+    let properties = unsafe {
+        context
+            .instance
+            .instance
+            .get_physical_device_memory_properties(context.physical_device)
+    };
+    (0..properties.memory_type_count).find(|&index| {
+        type_bits & (1 << index) != 0
+            && properties.memory_types[index as usize]
+                .property_flags
+                .contains(flags)
+    })
+}
+
+/// Allocates memory for `requirements`, the first of `preferences` the
+/// device has winning. `flags` is for memory that buffers with device
+/// addresses are bound to.
+fn allocate<'a>(
+    context: &'a Context,
+    requirements: vk::MemoryRequirements,
+    preferences: &[vk::MemoryPropertyFlags],
+    flags: vk::MemoryAllocateFlags,
+) -> Result<Owned<'a, vk::DeviceMemory>> {
+    let memory_type_index = preferences
+        .iter()
+        .find_map(|&flags| memory_type(context, requirements.memory_type_bits, flags))
+        .ok_or(Error::Vulkan {
+            call: "vkAllocateMemory",
+            result: vk::Result::ERROR_OUT_OF_DEVICE_MEMORY,
+        })?;
+    let mut flags_info = vk::MemoryAllocateFlagsInfo::builder().flags(flags);
+    let mut allocate_info = vk::MemoryAllocateInfo::builder()
+        .allocation_size(requirements.size)
+        .memory_type_index(memory_type_index);
+    if !flags.is_empty() {
+        allocate_info = allocate_info.push_next(&mut flags_info);
+    }
+    let memory = unsafe {
+        context
+            .device
+            .allocate_memory(&allocate_info, memory::callbacks())
+    }
+    .map_err(Error::vulkan("vkAllocateMemory"))?;
+    Ok(Owned::new(
+        &context.device,
+        memory,
+        ash::Device::free_memory,
+    ))
+}
+
+/// A buffer with memory of its own, host visible and coherent so it can be
+/// filled and read back by mapping, and device local where the device has
+/// such memory. Zeroed. The buffer is destroyed before the memory is freed.
+pub struct Buffer<'a> {
+    device: &'a ash::Device,
+    pub buffer: Owned<'a, vk::Buffer>,
+    pub memory: Owned<'a, vk::DeviceMemory>,
+    pub size: vk::DeviceSize,
+    /// Set when created with `SHADER_DEVICE_ADDRESS` usage.
+    pub address: Option<vk::DeviceAddress>,
+}
+
+impl<'a> Buffer<'a> {
+    pub fn new(
+        context: &'a Context,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+    ) -> Result<Self> {
+        let device = &context.device;
+        let buffer = unsafe {
+            device.create_buffer(
+                &vk::BufferCreateInfo::builder()
+                    .size(size)
+                    .usage(usage)
+                    .sharing_mode(vk::SharingMode::EXCLUSIVE),
+                memory::callbacks(),
+            )
+        }
+        .map_err(Error::vulkan("vkCreateBuffer"))?;
+        let buffer = Owned::new(device, buffer, ash::Device::destroy_buffer);
+
+        let host = vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+        let requirements = unsafe { device.get_buffer_memory_requirements(*buffer) };
+        let memory = allocate(
+            context,
+            requirements,
+            &[host | vk::MemoryPropertyFlags::DEVICE_LOCAL, host],
+            if usage.contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS) {
+                vk::MemoryAllocateFlags::DEVICE_ADDRESS
+            } else {
+                vk::MemoryAllocateFlags::empty()
+            },
+        )?;
+        unsafe { device.bind_buffer_memory(*buffer, *memory, 0) }
+            .map_err(Error::vulkan("vkBindBufferMemory"))?;
+        let address = usage
+            .contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS)
+            .then(|| unsafe {
+                device.get_buffer_device_address(
+                    &vk::BufferDeviceAddressInfo::builder().buffer(*buffer),
+                )
+            });
+
+        let buffer = Buffer {
+            device,
+            buffer,
+            memory,
+            size,
+            address,
+        };
+        buffer.write(&vec![0; size as usize])?;
+        Ok(buffer)
+    }
+
+    /// Copies `bytes` to the start of the buffer.
+    pub fn write(&self, bytes: &[u8]) -> Result<()> {
+        self.write_at(0, bytes)
+    }
+
+    /// Copies `bytes` to `offset`, as much as fits.
+    pub fn write_at(&self, offset: vk::DeviceSize, bytes: &[u8]) -> Result<()> {
+        let size = (bytes.len() as u64).min(self.size.saturating_sub(offset));
+        if size == 0 {
+            return Ok(());
+        }
+        unsafe {
+            let mapped = self
+                .device
+                .map_memory(*self.memory, offset, size, vk::MemoryMapFlags::empty())
+                .map_err(Error::vulkan("vkMapMemory"))?;
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), mapped.cast(), size as usize);
+            self.device.unmap_memory(*self.memory);
+        }
+        Ok(())
+    }
+
+    /// The whole contents.
+    pub fn read(&self) -> Result<Vec<u8>> {
+        let mut bytes = vec![0; self.size as usize];
+        unsafe {
+            let mapped = self
+                .device
+                .map_memory(*self.memory, 0, self.size, vk::MemoryMapFlags::empty())
+                .map_err(Error::vulkan("vkMapMemory"))?;
+            std::ptr::copy_nonoverlapping(mapped.cast(), bytes.as_mut_ptr(), bytes.len());
+            self.device.unmap_memory(*self.memory);
+        }
+        Ok(bytes)
+    }
+
+    /// The whole buffer, for a descriptor write.
+    pub fn descriptor_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo {
+            buffer: *self.buffer,
+            offset: 0,
+            range: self.size,
+        }
+    }
+}
+
+/// A 2D image in device local memory with a view of it, kept in the
+/// `GENERAL` layout. Contents go through a staging buffer.
+pub struct Image<'a> {
+    pub view: Owned<'a, vk::ImageView>,
+    pub image: Owned<'a, vk::Image>,
+    pub memory: Owned<'a, vk::DeviceMemory>,
+    pub extent: [u32; 2],
+}
+
+impl<'a> Image<'a> {
+    pub fn new(
+        context: &'a Context,
+        format: vk::Format,
+        extent: [u32; 2],
+        usage: vk::ImageUsageFlags,
+    ) -> Result<Self> {
+        let device = &context.device;
+        let image = unsafe {
+            device.create_image(
+                &vk::ImageCreateInfo::builder()
+                    .image_type(vk::ImageType::TYPE_2D)
+                    .format(format)
+                    .extent(vk::Extent3D {
+                        width: extent[0],
+                        height: extent[1],
+                        depth: 1,
+                    })
+                    .mip_levels(1)
+                    .array_layers(1)
+                    .samples(vk::SampleCountFlags::TYPE_1)
+                    .tiling(vk::ImageTiling::OPTIMAL)
+                    .usage(usage)
+                    .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                    .initial_layout(vk::ImageLayout::UNDEFINED),
+                memory::callbacks(),
+            )
+        }
+        .map_err(Error::vulkan("vkCreateImage"))?;
+        let image = Owned::new(device, image, ash::Device::destroy_image);
+
+        let requirements = unsafe { device.get_image_memory_requirements(*image) };
+        let memory = allocate(
+            context,
+            requirements,
+            &[
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                vk::MemoryPropertyFlags::empty(),
+            ],
+            vk::MemoryAllocateFlags::empty(),
+        )?;
+        unsafe { device.bind_image_memory(*image, *memory, 0) }
+            .map_err(Error::vulkan("vkBindImageMemory"))?;
+
+        let view = unsafe {
+            device.create_image_view(
+                &vk::ImageViewCreateInfo::builder()
+                    .image(*image)
+                    .view_type(vk::ImageViewType::TYPE_2D)
+                    .format(format)
+                    .subresource_range(Self::RANGE),
+                memory::callbacks(),
+            )
+        }
+        .map_err(Error::vulkan("vkCreateImageView"))?;
+        let view = Owned::new(device, view, ash::Device::destroy_image_view);
+
+        Ok(Image {
+            view,
+            image,
+            memory,
+            extent,
+        })
+    }
+
+    const RANGE: vk::ImageSubresourceRange = vk::ImageSubresourceRange {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count: 1,
+    };
+
+    fn copy(&self) -> vk::BufferImageCopy {
+        vk::BufferImageCopy {
+            image_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            image_extent: vk::Extent3D {
+                width: self.extent[0],
+                height: self.extent[1],
+                depth: 1,
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Uploads `staging` into the image, which ends up in `GENERAL`.
+    pub fn upload(&self, executor: &Executor, staging: &Buffer) -> Result<()> {
+        executor.submit(|device, command_buffer| unsafe {
+            let barrier = vk::ImageMemoryBarrier::builder()
+                .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .old_layout(vk::ImageLayout::UNDEFINED)
+                .new_layout(vk::ImageLayout::GENERAL)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(*self.image)
+                .subresource_range(Self::RANGE);
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[*barrier],
+            );
+            device.cmd_copy_buffer_to_image(
+                command_buffer,
+                *staging.buffer,
+                *self.image,
+                vk::ImageLayout::GENERAL,
+                &[self.copy()],
+            );
+            let barrier = vk::MemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[*barrier],
+                &[],
+                &[],
+            );
+        })?;
+        Ok(())
+    }
+
+    /// Copies the image into `staging`, which must be large enough.
+    pub fn download(&self, executor: &Executor, staging: &Buffer) -> Result<()> {
+        executor.submit(|device, command_buffer| unsafe {
+            device.cmd_copy_image_to_buffer(
+                command_buffer,
+                *self.image,
+                vk::ImageLayout::GENERAL,
+                *staging.buffer,
+                &[self.copy()],
+            );
+            let barrier = vk::MemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::HOST_READ);
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[*barrier],
+                &[],
+                &[],
+            );
+        })?;
+        Ok(())
+    }
+}
+
+/// The Vulkan objects behind a resource. A texel buffer has a view, which is
+/// destroyed before the buffer.
+pub enum Object<'a> {
+    Buffer {
+        view: Option<Owned<'a, vk::BufferView>>,
+        buffer: Buffer<'a>,
+    },
+    Image {
+        image: Image<'a>,
+        staging: Buffer<'a>,
+    },
+}
+
+/// A created resource and the spec it came from.
+pub struct Resource<'a> {
+    pub spec: Spec,
+    pub object: Object<'a>,
+}
+
+impl<'a> Resource<'a> {
+    /// Creates the resource and uploads its initial contents. Images keep
+    /// their staging buffer for reading back.
+    pub fn new(
+        context: &'a Context,
+        executor: &Executor,
+        spec: &Spec,
+        rng: &mut StdRng,
+    ) -> Result<Self> {
+        let contents = spec.init.contents(spec.size, rng)?;
+        let object = if spec.kind.is_image() {
+            let image = Image::new(context, spec.format, spec.extent, spec.kind.image_usage())?;
+            let staging = Buffer::new(
+                context,
+                spec.size,
+                vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST,
+            )?;
+            staging.write(&contents)?;
+            image.upload(executor, &staging)?;
+            Object::Image { image, staging }
+        } else {
+            let mut usage = spec.kind.buffer_usage();
+            if spec.device_address {
+                usage |= vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS;
+            }
+            let buffer = Buffer::new(context, spec.size, usage)?;
+            buffer.write(&contents)?;
+            let view = match spec.format {
+                vk::Format::UNDEFINED => None,
+                format => {
+                    let view = unsafe {
+                        context.device.create_buffer_view(
+                            &vk::BufferViewCreateInfo::builder()
+                                .buffer(*buffer.buffer)
+                                .format(format)
+                                .range(vk::WHOLE_SIZE),
+                            memory::callbacks(),
+                        )
+                    }
+                    .map_err(Error::vulkan("vkCreateBufferView"))?;
+                    Some(Owned::new(
+                        &context.device,
+                        view,
+                        ash::Device::destroy_buffer_view,
+                    ))
+                }
+            };
+            Object::Buffer { view, buffer }
+        };
+        Ok(Resource {
+            spec: spec.clone(),
+            object,
+        })
+    }
+
+    /// The current contents. Images are copied out through their staging
+    /// buffer first.
+    pub fn read(&self, executor: &Executor) -> Result<Vec<u8>> {
+        match &self.object {
+            Object::Buffer { buffer, .. } => buffer.read(),
+            Object::Image { image, staging } => {
+                image.download(executor, staging)?;
+                staging.read()
+            }
+        }
+    }
+}
+
+/// The layout of the set `spec` is bound in at `slot`, checking the binding
+/// is there and takes `spec`'s kind of descriptor.
+fn target<'l>(spec: &Spec, slot: Slot, set_layouts: &'l [SetLayout]) -> Result<&'l SetLayout> {
+    let layout = set_layouts.get(slot.set as usize).ok_or_else(|| {
+        Error::Usage(format!(
+            "[resource {}]: the layout has no set {}",
+            spec.name, slot.set
+        ))
+    })?;
+    let binding = layout.bindings.get(slot.binding as usize).ok_or_else(|| {
+        Error::Usage(format!(
+            "[resource {}]: set {} has no binding {}",
+            spec.name, slot.set, slot.binding
+        ))
+    })?;
+    let descriptor_type = spec.kind.descriptor_type();
+    let fits = binding.descriptor_type == descriptor_type
+        || binding.descriptor_type == vk::DescriptorType::MUTABLE_VALVE
+            && binding.mutable_types.contains(&descriptor_type);
+    if !fits {
+        return Err(Error::Usage(format!(
+            "[resource {}]: a {:?} can't go in set {} binding {}, a {:?}",
+            spec.name, descriptor_type, slot.set, slot.binding, binding.descriptor_type
+        )));
+    }
+    Ok(layout)
+}
+
+/// Every resource of a run, destroyed together.
+#[derive(Default)]
+pub struct Resources<'a> {
+    pub resources: Vec<Resource<'a>>,
+}
+
+impl<'a> Resources<'a> {
+    pub fn new(
+        context: &'a Context,
+        executor: &Executor,
+        specs: &[Spec],
+        rng: &mut StdRng,
+    ) -> Result<Self> {
+        let resources = specs
+            .iter()
+            .map(|spec| Resource::new(context, executor, spec, rng))
+            .collect::<Result<Vec<_>>>()?;
+        let resources = Resources { resources };
+
+        for resource in &resources.resources {
+            let Object::Buffer { buffer, .. } = &resource.object else {
+                continue;
+            };
+            for (offset, name) in &resource.spec.addresses {
+                let address = resources.address(name).ok_or_else(|| {
+                    Error::Usage(format!(
+                        "[resource {}]: address@{}: no [resource {}] with device-address",
+                        resource.spec.name, offset, name
+                    ))
+                })?;
+                buffer.write_at(*offset, &address.to_le_bytes())?;
+            }
+        }
+        Ok(resources)
+    }
+
+    /// The device address of buffer `name`, if it was created with one.
+    pub fn address(&self, name: &str) -> Option<vk::DeviceAddress> {
+        self.resources
+            .iter()
+            .find(|resource| resource.spec.name == name)
+            .and_then(|resource| match &resource.object {
+                Object::Buffer { buffer, .. } => buffer.address,
+                Object::Image { .. } => None,
+            })
+    }
+
+    /// The resources shaders can write.
+    pub fn writable(&self) -> impl Iterator<Item = &Resource<'a>> {
+        self.resources
+            .iter()
+            .filter(|resource| resource.spec.kind.is_writable())
+    }
+
+    /// Writes the descriptors of bound resources into `sets`, laid out as
+    /// `set_layouts`. Resources bound in a push descriptor set are returned
+    /// to be pushed instead; only buffers can be.
+    pub fn bind(
+        &self,
+        device: &ash::Device,
+        sets: &[vk::DescriptorSet],
+        set_layouts: &[SetLayout],
+    ) -> Result<Vec<Pushed>> {
+        let mut pushed = vec![];
+        for resource in &self.resources {
+            let Some(slot) = resource.spec.slot else {
+                continue;
+            };
+            let descriptor_type = resource.spec.kind.descriptor_type();
+            let layout = target(&resource.spec, slot, set_layouts)?;
+            if layout
+                .flags
+                .contains(vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR)
+            {
+                let Object::Buffer { view: None, buffer } = &resource.object else {
+                    return Err(Error::Usage(format!(
+                        "[resource {}]: only buffers can go in push descriptor set {}",
+                        resource.spec.name, slot.set
+                    )));
+                };
+                pushed.push(Pushed {
+                    set: slot.set,
+                    binding: slot.binding,
+                    descriptor_type,
+                    buffer_info: buffer.descriptor_info(),
+                });
+                continue;
+            }
+            let set = match sets.get(slot.set as usize) {
+                Some(&set) if set != vk::DescriptorSet::null() => set,
+                _ => {
+                    return Err(Error::Usage(format!(
+                        "[resource {}]: set {} was not allocated",
+                        resource.spec.name, slot.set
+                    )))
+                }
+            };
+
+            let buffer_info;
+            let texel_view;
+            let image_info;
+            let write = vk::WriteDescriptorSet::builder()
+                .dst_set(set)
+                .dst_binding(slot.binding)
+                .dst_array_element(slot.element)
+                .descriptor_type(descriptor_type);
+            let write = match &resource.object {
+                Object::Buffer {
+                    view: Some(view), ..
+                } => {
+                    texel_view = [**view];
+                    write.texel_buffer_view(&texel_view)
+                }
+                Object::Buffer { view: None, buffer } => {
+                    buffer_info = [buffer.descriptor_info()];
+                    write.buffer_info(&buffer_info)
+                }
+                Object::Image { image, .. } => {
+                    image_info = [vk::DescriptorImageInfo {
+                        sampler: vk::Sampler::null(),
+                        image_view: *image.view,
+                        image_layout: vk::ImageLayout::GENERAL,
+                    }];
+                    write.image_info(&image_info)
+                }
+            };
+            unsafe { device.update_descriptor_sets(&[*write], &[]) };
+        }
+        Ok(pushed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(entries: &[(&str, &str)]) -> case::Section {
+        case::Section {
+            kind: "resource".to_string(),
+            name: "data".to_string(),
+            entries: entries
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn formats_only_for_texel_buffers_and_images() {
+        let spec = Spec::from_section(&section(&[
+            ("kind", "storage-texel-buffer"),
+            ("format", "r32-uint"),
+            ("size", "64"),
+        ]))
+        .unwrap();
+        assert_eq!(spec.format, vk::Format::R32_UINT);
+        let spec =
+            Spec::from_section(&section(&[("kind", "storage-buffer"), ("size", "64")])).unwrap();
+        assert_eq!(spec.format, vk::Format::UNDEFINED);
+
+        for kind in ["storage-buffer", "uniform-buffer"] {
+            let error = Spec::from_section(&section(&[
+                ("kind", kind),
+                ("format", "r32-uint"),
+                ("size", "64"),
+            ]))
+            .unwrap_err();
+            assert!(
+                matches!(&error, Error::Usage(message) if message.starts_with("[resource data]")),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn bindings_have_to_exist_and_fit() {
+        let layout = crate::LayoutBuilder::captured()
+            .push_descriptors(3)
+            .allocatable();
+        let spec = |kind: &str, bind: &str| {
+            Spec::from_section(&section(&[("kind", kind), ("size", "64"), ("bind", bind)])).unwrap()
+        };
+        let check =
+            |spec: &Spec| target(spec, spec.slot.unwrap(), &layout.sets).map(|set| set.flags);
+
+        assert_eq!(
+            check(&spec("uniform-buffer", "3.0")).unwrap(),
+            vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR
+        );
+        assert!(check(&spec("storage-buffer", "1.0")).is_ok());
+        // Through the mutable binding's type list.
+        assert!(check(&spec("storage-buffer", "1.2.7")).is_ok());
+
+        for bind in ["4.0", "3.1", "0.0"] {
+            let error = check(&spec("uniform-buffer", bind)).unwrap_err();
+            assert!(
+                matches!(&error, Error::Usage(message) if message.starts_with("[resource data]")),
+                "{}: {}",
+                bind,
+                error
+            );
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use std::{
+    fmt,
+    os::unix::process::ExitStatusExt,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use crate::{
+    baseline,
+    error::{self, Error, Result},
+};
+
+/// A directory under the system temp dir, removed again on drop.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(purpose: &str) -> Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "vk-compute-shader-testing-{}-{}-{}",
+            std::process::id(),
+            purpose,
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path).map_err(Error::io(&path))?;
+        Ok(TempDir(path))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// How a child run ended.
+#[derive(Clone, Debug)]
+pub enum Outcome {
+    /// The child wrote its batch records.
+    Completed(Vec<baseline::Record>),
+    /// Killed by a signal, usually the driver crashing.
+    Crashed(i32),
+    /// Exited without writing records, e.g. a panic on a Vulkan error.
+    Failed(Option<i32>),
+    /// Exited with `error::SKIPPED`, the device can't run the case.
+    Skipped,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Completed(records) => {
+                let outcomes = records
+                    .iter()
+                    .map(|record| record.outcome.to_string())
+                    .collect::<Vec<_>>();
+                write!(f, "{}", outcomes.join(", "))
+            }
+            Outcome::Crashed(signal) => write!(f, "crash (signal {})", signal),
+            Outcome::Failed(Some(code)) => match Error::stage(*code) {
+                Some(stage) => write!(f, "error (exit {}, {})", code, stage),
+                None => write!(f, "error (exit {})", code),
+            },
+            Outcome::Failed(None) => write!(f, "error"),
+            Outcome::Skipped => write!(f, "skipped"),
+        }
+    }
+}
+
+/// Total size in bytes of the files under `path`. Mesa's cache is either a
+/// tree of small files or one growing file, so size catches both.
+pub fn disk_usage(path: &Path) -> u64 {
+    std::fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => disk_usage(&entry.path()),
+            _ => entry.metadata().map_or(0, |metadata| metadata.len()),
+        })
+        .sum()
+}
+
+/// Whether a run could have been served from Mesa's shader cache.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheState {
+    /// The cache directory was empty.
+    Cold,
+    /// The cache had entries and the run added none.
+    Warm,
+    /// The cache had entries but the run still wrote new ones.
+    Miss,
+}
+
+impl CacheState {
+    fn new(before: u64, after: u64) -> Self {
+        match (before, after) {
+            (0, _) => CacheState::Cold,
+            (before, after) if after > before => CacheState::Miss,
+            _ => CacheState::Warm,
+        }
+    }
+
+    /// Describes the state, flagging warm runs that were not clearly faster
+    /// than `cold`, the compile time of a cold run of the same shader.
+    pub fn describe(self, compile_time: Option<Duration>, cold: Option<Duration>) -> String {
+        match (self, compile_time, cold) {
+            (CacheState::Warm, Some(time), Some(cold)) if time * 2 > cold => {
+                format!("warm, but {:?} against {:?} cold", time, cold)
+            }
+            (state, _, _) => format!("{:?}", state).to_lowercase(),
+        }
+    }
+}
+
+/// Result of one child process.
+pub struct Run {
+    pub outcome: Outcome,
+    /// `name = value` pairs the child printed for `--stats`, each name led by
+    /// the executable it is for, e.g. `1 SIMD16 Instruction Count`.
+    pub statistics: Vec<(String, String)>,
+    /// The driver relevant environment the child reported.
+    pub environment: Vec<(String, String)>,
+    /// The allocations the child failed on purpose, as it described them.
+    pub faults: Vec<String>,
+    /// The Vulkan result the child exited with, when it had injected faults.
+    pub result: Option<String>,
+    pub cache: CacheState,
+    /// The layout changes of a `--fuzz-layout` child.
+    pub mutations: Vec<String>,
+    /// What the validation layer reported, from either stream.
+    pub validation: Vec<String>,
+}
+
+/// The statistics in what `executable::report` printed. A pipeline can have
+/// several executables with the same statistics, e.g. one per SIMD width on
+/// anv, so the names are qualified with the executable's index and name.
+fn statistics(stdout: &str) -> Vec<(String, String)> {
+    let mut executable = String::new();
+    let mut statistics = vec![];
+    for line in stdout.lines() {
+        if let Some((_, rest)) = line.split_once(": executable ") {
+            executable = rest.split(" (").next().unwrap_or(rest).to_string();
+        } else if let Some((name, value)) = line
+            .strip_prefix("    ")
+            .and_then(|line| line.split_once(" = "))
+        {
+            statistics.push((format!("{} {}", executable, name), value.to_string()));
+        }
+    }
+    statistics
+}
+
+/// Runs this executable again as `batch` with `args`, in a fresh process so a
+/// driver crash only takes down the child. `env` is added to the child's
+/// environment.
+///
+/// Each run gets an empty Mesa shader cache unless `shader_cache` names a
+/// directory to share between runs.
+pub fn run(args: &[String], env: &[(String, String)], shader_cache: Option<&Path>) -> Result<Run> {
+    let dir = TempDir::new("run")?;
+    let records_path = dir.path().join("records.txt");
+    let shader_cache = match shader_cache {
+        Some(path) => path.to_path_buf(),
+        None => dir.path().join("shader-cache"),
+    };
+    let before = disk_usage(&shader_cache);
+
+    let executable = std::env::current_exe().map_err(Error::CurrentExe)?;
+    let output = Command::new(&executable)
+        .arg("batch")
+        .args(args)
+        .arg(format!("--shader-cache={}", shader_cache.display()))
+        .arg(format!("--baseline={}", records_path.display()))
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .output()
+        .map_err(Error::io(&executable))?;
+    let cache = CacheState::new(before, disk_usage(&shader_cache));
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let statistics = statistics(&stdout);
+    let environment = stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("env ")?.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    let faults = stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("fault "))
+        .map(str::to_string)
+        .collect();
+    let result = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("result "))
+        .map(str::to_string);
+    let mutations = stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("mutation "))
+        .map(str::to_string)
+        .collect();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let validation = stdout
+        .lines()
+        .chain(stderr.lines())
+        .filter(|line| line.contains("Validation Error"))
+        .map(str::to_string)
+        .collect();
+
+    let outcome = match (output.status.signal(), baseline::load(&records_path)) {
+        (Some(signal), _) => Outcome::Crashed(signal),
+        (None, Ok(records)) if output.status.success() => Outcome::Completed(records),
+        (None, _) if output.status.code() == Some(error::SKIPPED as i32) => Outcome::Skipped,
+        (None, _) => {
+            eprint!("{}", stderr);
+            Outcome::Failed(output.status.code())
+        }
+    };
+
+    Ok(Run {
+        outcome,
+        statistics,
+        environment,
+        faults,
+        result,
+        cache,
+        mutations,
+        validation,
+    })
+}
+
+/// Prints `rows` under `header` as a table with aligned columns.
+pub fn print_table(header: &[String], rows: &[Vec<String>]) {
+    let widths = header
+        .iter()
+        .enumerate()
+        .map(|(i, title)| {
+            rows.iter()
+                .map(|row| row[i].len())
+                .chain([title.len()])
+                .max()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    for row in [header].into_iter().chain(rows.iter().map(Vec::as_slice)) {
+        let cells = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>();
+        println!("{}", cells.join(" | ").trim_end());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statistics_are_per_executable() {
+        let stdout = "\
+shaders/1.spv: executable 0 SIMD8 (SIMD8 Compute Shader), subgroup size 8
+    Instruction Count = 120
+    SEND Count = 4
+shaders/1.spv: executable 1 SIMD16 (SIMD16 Compute Shader), subgroup size 16
+    Instruction Count = 140
+";
+        assert_eq!(
+            statistics(stdout),
+            [
+                ("0 SIMD8 Instruction Count", "120"),
+                ("0 SIMD8 SEND Count", "4"),
+                ("1 SIMD16 Instruction Count", "140"),
+            ]
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+        );
+    }
+}
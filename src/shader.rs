@@ -0,0 +1,119 @@
+use std::path::{Path, PathBuf};
+
+use nom::Parser;
+
+use crate::error::{Error, Result};
+
+const SPIRV_MAGIC: u32 = 0x07230203;
+const OP_EXECUTION_MODE: u32 = 16;
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+
+/// Loads SPIR-V binaries. Relative paths are resolved against `root`, which
+/// is the working directory by default; embedders pointing at
+/// [`crate::SHADERS`] want the crate root here.
+#[derive(Clone, Debug, Default)]
+pub struct ShaderLoader {
+    root: PathBuf,
+}
+
+impl ShaderLoader {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        ShaderLoader { root: root.into() }
+    }
+
+    pub fn load(&self, path: &str) -> Result<Vec<u32>> {
+        let path = self.root.join(path);
+        let bytes = std::fs::read(&path).map_err(|source| Error::ShaderLoad {
+            path: path.clone(),
+            source,
+        })?;
+        Self::parse(&path, &bytes)
+    }
+
+    /// Splits `bytes` into words, checking it looks like SPIR-V. `path` is
+    /// only used for errors.
+    pub fn parse(path: &Path, bytes: &[u8]) -> Result<Vec<u32>> {
+        let spirv_parse = |reason| Error::SpirvParse {
+            path: path.into(),
+            reason,
+        };
+        if !bytes.len().is_multiple_of(4) {
+            return Err(spirv_parse(format!(
+                "{} bytes is not a whole number of words",
+                bytes.len()
+            )));
+        }
+        let (_, code) =
+            nom::multi::many0(nom::number::complete::u32::<_, nom::error::Error<&[u8]>>(
+                nom::number::Endianness::Native,
+            ))
+            .parse(bytes)
+            .map_err(|e| spirv_parse(e.to_string()))?;
+        match code.first() {
+            Some(&SPIRV_MAGIC) => Ok(code),
+            Some(&magic) => Err(spirv_parse(format!("bad magic number {:#010x}", magic))),
+            None => Err(spirv_parse("empty file".into())),
+        }
+    }
+
+    /// The workgroup size `code` declares with a literal `LocalSize`
+    /// execution mode; `None` for `LocalSizeId` or anything unreadable.
+    pub fn local_size(code: &[u32]) -> Option<[u32; 3]> {
+        let mut index = 5;
+        while let Some(&first) = code.get(index) {
+            let length = (first >> 16) as usize;
+            if length == 0 {
+                return None;
+            }
+            let operands = code.get(index + 1..index + length)?;
+            if first & 0xffff == OP_EXECUTION_MODE {
+                if let [_, EXECUTION_MODE_LOCAL_SIZE, x, y, z] = *operands {
+                    return Some([x, y, z]);
+                }
+            }
+            index += length;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_size() {
+        let header = [SPIRV_MAGIC, 0x0001_0000, 0, 10, 0];
+        // OpExecutionMode %4 OriginUpperLeft, then %4 LocalSize 64 2 1.
+        let code = [
+            &header[..],
+            &[3 << 16 | OP_EXECUTION_MODE, 4, 7],
+            &[
+                6 << 16 | OP_EXECUTION_MODE,
+                4,
+                EXECUTION_MODE_LOCAL_SIZE,
+                64,
+                2,
+                1,
+            ],
+        ]
+        .concat();
+        assert_eq!(ShaderLoader::local_size(&code), Some([64, 2, 1]));
+        assert_eq!(ShaderLoader::local_size(&code[..code.len() - 1]), None);
+        assert_eq!(ShaderLoader::local_size(&header), None);
+        // LocalSizeId has ids, not sizes.
+        let code = [&header[..], &[6 << 16 | OP_EXECUTION_MODE, 4, 38, 5, 6, 7]].concat();
+        assert_eq!(ShaderLoader::local_size(&code), None);
+    }
+
+    #[test]
+    fn captured_shaders_declare_their_local_size() {
+        let loader = ShaderLoader::new(env!("CARGO_MANIFEST_DIR"));
+        let sizes =
+            crate::SHADERS.map(|path| ShaderLoader::local_size(&loader.load(path).unwrap()));
+        assert_eq!(
+            sizes,
+            [Some([32, 1, 1]), Some([64, 1, 1]), Some([32, 1, 1])]
+        );
+    }
+}
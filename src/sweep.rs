@@ -0,0 +1,138 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::{
+    driconf,
+    error::{Error, Result},
+    runner,
+};
+
+/// Every combination of the swept option values, in command line order.
+fn combinations(options: &[(String, Vec<String>)]) -> Vec<Vec<driconf::DriOption>> {
+    options
+        .iter()
+        .fold(vec![vec![]], |combinations, (name, values)| {
+            combinations
+                .iter()
+                .flat_map(|combination| {
+                    values.iter().map(move |value| {
+                        let mut combination: Vec<driconf::DriOption> = combination.clone();
+                        combination.push(driconf::DriOption {
+                            comments: vec![],
+                            name: name.clone(),
+                            value: value.clone(),
+                        });
+                        combination
+                    })
+                })
+                .collect()
+        })
+}
+
+/// Compiles each of `shaders` (indices into `SHADERS`) in a child process per
+/// option combination, with `DRIRC_CONFIGDIR` pointing at a generated driconf
+/// for `driver` and `application_name` on top of `env`, and prints a table of
+/// outcomes and statistics. `shader_cache` is passed on to `runner::run`.
+pub fn run(
+    driver: &str,
+    application_name: &str,
+    options: &[(String, Vec<String>)],
+    shaders: &[usize],
+    args: &[String],
+    env: &[(String, String)],
+    shader_cache: Option<&Path>,
+) -> Result<()> {
+    let mut cold_times = HashMap::new();
+    let mut rows = vec![];
+    for &shader in shaders {
+        for combination in combinations(options) {
+            let setting = combination
+                .iter()
+                .map(|option| format!("{}={}", option.name, option.value))
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            let dir = runner::TempDir::new("driconf")?;
+            let mut config = driconf::Driconf::default();
+            config.merge(
+                driver,
+                driconf::Application::for_tool(application_name, combination),
+            );
+            let config_path = dir.path().join("sweep.conf");
+            std::fs::write(&config_path, config.to_string()).map_err(Error::io(&config_path))?;
+
+            let mut child_args = vec![format!("-{}", shader + 1), "--stats".to_string()];
+            child_args.extend_from_slice(args);
+            let mut child_env = env.to_vec();
+            child_env.push((
+                "DRIRC_CONFIGDIR".to_string(),
+                dir.path().to_string_lossy().into_owned(),
+            ));
+            let run = runner::run(&child_args, &child_env, shader_cache)?;
+            let compile_time = match &run.outcome {
+                runner::Outcome::Completed(records) => {
+                    records.first().map(|record| record.compile_time)
+                }
+                _ => None,
+            };
+            if let (runner::CacheState::Cold, Some(time)) = (run.cache, compile_time) {
+                cold_times.entry(shader).or_insert(time);
+            }
+            let cache = run
+                .cache
+                .describe(compile_time, cold_times.get(&shader).copied());
+            eprintln!("{} {}: {}, {}", shader + 1, setting, run.outcome, cache);
+            for (key, value) in &run.environment {
+                eprintln!("    {}={}", key, value);
+            }
+            rows.push((shader, setting, run, cache));
+        }
+    }
+
+    let mut columns = vec![];
+    for (_, _, run, _) in &rows {
+        for (name, _) in &run.statistics {
+            if !columns.contains(name) {
+                columns.push(name.clone());
+            }
+        }
+    }
+
+    let table = rows
+        .iter()
+        .map(|(shader, setting, run, cache)| {
+            let time = match &run.outcome {
+                runner::Outcome::Completed(records) => records
+                    .iter()
+                    .map(|record| format!("{:?}", record.compile_time))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                _ => "-".to_string(),
+            };
+            let mut row = vec![
+                (shader + 1).to_string(),
+                setting.clone(),
+                run.outcome.to_string(),
+                time,
+                cache.clone(),
+            ];
+            row.extend(columns.iter().map(|column| {
+                run.statistics
+                    .iter()
+                    .find(|(name, _)| name == column)
+                    .map_or_else(|| "-".to_string(), |(_, value)| value.clone())
+            }));
+            row
+        })
+        .collect::<Vec<_>>();
+
+    let mut header = vec![
+        "shader".to_string(),
+        "setting".to_string(),
+        "outcome".to_string(),
+        "time".to_string(),
+        "shader cache".to_string(),
+    ];
+    header.extend(columns);
+    runner::print_table(&header, &table);
+    Ok(())
+}